@@ -0,0 +1,252 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display},
+    time::{Duration, Instant},
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+
+use super::stock::BaseData;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AlertCondition {
+    PriceAbove(f64),
+    PriceBelow(f64),
+    /// `rise_per` at or above the percentage.
+    RiseAbove(f64),
+    /// `rise_per` at or below minus the percentage.
+    FallBelow(f64),
+    VolumeAbove(u64),
+}
+
+impl AlertCondition {
+    /// The watched value, and whether the condition holds with the
+    /// threshold moved back by `margin`.
+    fn check(&self, data: &BaseData, margin: f64) -> (f64, bool) {
+        let new = data.new as f64;
+        let rise = data.rise_per as f64;
+        match *self {
+            AlertCondition::PriceAbove(x) => (new, new >= x - margin),
+            AlertCondition::PriceBelow(x) => (new, new <= x + margin),
+            AlertCondition::RiseAbove(x) => (rise, rise >= x - margin),
+            AlertCondition::FallBelow(x) => (rise, rise <= -x + margin),
+            AlertCondition::VolumeAbove(x) => {
+                let vol = data.vol as f64;
+                (vol, vol >= x as f64 - margin)
+            }
+        }
+    }
+}
+
+impl Display for AlertCondition {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AlertCondition::PriceAbove(x) => write!(f, "price ≥ {}", x),
+            AlertCondition::PriceBelow(x) => write!(f, "price ≤ {}", x),
+            AlertCondition::RiseAbove(x) => write!(f, "rise ≥ {}%", x),
+            AlertCondition::FallBelow(x) => write!(f, "fall ≥ {}%", x),
+            AlertCondition::VolumeAbove(x) => write!(f, "volume ≥ {}", x),
+        }
+    }
+}
+
+/// Where an alert goes besides the in-window history.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Channels {
+    /// freedesktop notification over D-Bus
+    pub desktop: bool,
+    /// the alert sound file
+    pub sound: bool,
+    /// the outgoing webhook
+    pub webhook: bool,
+}
+
+/// A persisted alert on one code.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub id: u64,
+    pub code: String,
+    pub condition: AlertCondition,
+    pub enabled: bool,
+    /// Minimum time between two alerts of this rule.
+    pub cooldown_secs: u64,
+    /// How far the value has to move back past the threshold, in the
+    /// condition's unit, before the rule can fire again.
+    pub hysteresis: f64,
+    #[serde(default)]
+    pub channels: Channels,
+}
+
+/// A fired rule.
+#[derive(Clone, Debug)]
+pub struct Alert {
+    pub rule_id: u64,
+    pub code: String,
+    pub name: String,
+    pub condition: AlertCondition,
+    pub value: f64,
+    pub at: DateTime<Local>,
+    pub channels: Channels,
+    /// The quote that fired the rule.
+    pub data: BaseData,
+}
+
+impl Display for Alert {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} {}({}) {} at {}",
+            self.at.format("%H:%M:%S"),
+            self.name,
+            self.code,
+            self.condition,
+            self.value
+        )
+    }
+}
+
+#[derive(Clone, Debug, Default)]
+struct RuleState {
+    /// `None` until the first quote, so rules already met when added or at
+    /// startup wait for the next crossing.
+    armed: Option<bool>,
+    last_fired: Option<Instant>,
+}
+
+/// Evaluates the rules against every batch of quotes.
+#[derive(Clone, Debug, Default)]
+pub struct AlertEngine {
+    rules: Vec<AlertRule>,
+    states: HashMap<u64, RuleState>,
+}
+
+impl AlertEngine {
+    /// Replace the rules, keeping the state of those that did not change.
+    pub fn set_rules(&mut self, rules: Vec<AlertRule>) {
+        let old = std::mem::take(&mut self.rules);
+        self.states.retain(|id, _| {
+            let before = old.iter().find(|r| r.id == *id);
+            rules.iter().any(|r| Some(r) == before)
+        });
+        self.rules = rules;
+    }
+
+    pub fn evaluate<'a>(
+        &mut self,
+        quotes: impl IntoIterator<Item = (&'a str, &'a str, &'a BaseData)>,
+    ) -> Vec<Alert> {
+        let now = Instant::now();
+        let mut alerts = vec![];
+        for (code, name, data) in quotes {
+            // suspended or not yet opened, the price is a placeholder
+            if data.new <= 0.0 {
+                continue;
+            }
+            for rule in self.rules.iter().filter(|r| r.enabled && r.code == code) {
+                let state = self.states.entry(rule.id).or_default();
+                let (value, met) = rule.condition.check(data, 0.0);
+                let Some(armed) = state.armed else {
+                    state.armed = Some(!met);
+                    continue;
+                };
+                if !armed {
+                    let (_, still) = rule.condition.check(data, rule.hysteresis.abs());
+                    state.armed = Some(!still);
+                    continue;
+                }
+                let cooling = state.last_fired.is_some_and(|t| {
+                    now.duration_since(t) < Duration::from_secs(rule.cooldown_secs)
+                });
+                if met && !cooling {
+                    state.armed = Some(false);
+                    state.last_fired = Some(now);
+                    alerts.push(Alert {
+                        rule_id: rule.id,
+                        code: code.to_string(),
+                        name: name.to_string(),
+                        condition: rule.condition,
+                        value,
+                        at: Local::now(),
+                        channels: rule.channels,
+                        data: data.clone(),
+                    });
+                }
+            }
+        }
+        alerts
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine(cooldown_secs: u64) -> AlertEngine {
+        let mut engine = AlertEngine::default();
+        engine.set_rules(vec![AlertRule {
+            id: 1,
+            code: "sh600519".to_string(),
+            condition: AlertCondition::PriceAbove(100.0),
+            enabled: true,
+            cooldown_secs,
+            hysteresis: 2.0,
+            channels: Channels::default(),
+        }]);
+        engine
+    }
+
+    /// How many alerts each price fires, in order.
+    fn run(engine: &mut AlertEngine, prices: &[f32]) -> Vec<usize> {
+        prices
+            .iter()
+            .map(|&new| {
+                let data = BaseData {
+                    new,
+                    ..BaseData::default()
+                };
+                engine.evaluate([("sh600519", "贵州茅台", &data)]).len()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn fires_on_crossing_only() {
+        let mut engine = engine(0);
+        // already met on the first quote, then crossed from below
+        assert_eq!(
+            run(&mut engine, &[101.0, 97.0, 99.0, 100.0, 101.0]),
+            [0, 0, 0, 1, 0]
+        );
+    }
+
+    #[test]
+    fn rearms_past_the_hysteresis_band() {
+        let mut engine = engine(0);
+        // 98.5 is still within 2 of the threshold, 97.5 is past it
+        assert_eq!(
+            run(&mut engine, &[95.0, 100.5, 98.5, 100.5, 97.5, 100.5]),
+            [0, 1, 0, 0, 0, 1]
+        );
+    }
+
+    #[test]
+    fn cooldown_suppresses_refiring() {
+        let mut engine = engine(3600);
+        assert_eq!(
+            run(&mut engine, &[95.0, 100.5, 97.0, 100.5, 101.0]),
+            [0, 1, 0, 0, 0]
+        );
+    }
+
+    #[test]
+    fn skips_quotes_without_a_price() {
+        let mut engine = engine(0);
+        // a zero price would otherwise re-arm the rule
+        assert_eq!(
+            run(&mut engine, &[95.0, 0.0, 100.5, 0.0, 100.5]),
+            [0, 0, 1, 0, 0]
+        );
+    }
+}
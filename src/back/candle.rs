@@ -0,0 +1,241 @@
+//! Minute bars built locally from polled quote snapshots.
+//!
+//! Quotes arrive several times a second while klines are refetched once a
+//! minute, so the forming bar is kept up to date here between fetches.
+//! Snapshots carry the day's cumulative volume and amount; a bar gets the
+//! growth of both while it is open.
+
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeDelta};
+use eframe::egui::ahash::HashMap;
+
+use super::stock::{BaseData, KLineScale, KlineItem};
+
+/// Scales short enough to be worth building between fetches.
+pub const LOCAL_SCALES: [KLineScale; 3] = [
+    KLineScale::Intraday,
+    KLineScale::Munute5,
+    KLineScale::Munute15,
+];
+
+/// A-share continuous trading, 9:30-11:30 and 13:00-15:00.
+const SESSIONS: [((u32, u32), (u32, u32)); 2] = [((9, 30), (11, 30)), ((13, 0), (15, 0))];
+
+#[derive(Clone, Debug)]
+struct Snapshot {
+    at: NaiveDateTime,
+    vol: f64,
+    amount: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct CandleBuilder {
+    last: HashMap<String, Snapshot>,
+    bars: HashMap<(String, KLineScale), KlineItem>,
+}
+
+impl CandleBuilder {
+    /// Fold a snapshot of `code` into its bars. Returns whether anything
+    /// moved; a repeated or stale snapshot changes nothing.
+    pub fn update(&mut self, code: &str, data: &BaseData) -> bool {
+        let Some(at) = snapshot_time(data) else {
+            return false;
+        };
+        let snapshot = Snapshot {
+            at,
+            vol: data.vol as f64,
+            amount: data.amount as f64,
+        };
+        // cumulative figures restart every day, and the first snapshot we
+        // see has no predecessor to measure against
+        let (volume, amount) = match self.last.get(code) {
+            Some(prev) if prev.at >= at => return false,
+            Some(prev) if prev.at.date() == at.date() => (
+                (snapshot.vol - prev.vol).max(0.0),
+                (snapshot.amount - prev.amount).max(0.0),
+            ),
+            _ => (0.0, 0.0),
+        };
+        self.last.insert(code.to_string(), snapshot);
+
+        let price = data.new as f64;
+        if price <= 0.0 {
+            return false;
+        }
+        for scale in LOCAL_SCALES {
+            let day = bucket(at, scale.to_usize() as i64);
+            let key = (code.to_string(), scale);
+            match self.bars.get_mut(&key) {
+                Some(bar) if bar.day == day => {
+                    bar.high = bar.high.max(price);
+                    bar.low = bar.low.min(price);
+                    bar.close = price;
+                    bar.volume += volume;
+                    bar.amount += amount;
+                }
+                _ => {
+                    self.bars.insert(
+                        key,
+                        KlineItem {
+                            day,
+                            open: price,
+                            high: price,
+                            low: price,
+                            close: price,
+                            volume,
+                            amount,
+                        },
+                    );
+                }
+            }
+        }
+        true
+    }
+
+    /// The forming bar of `code` at `scale`, if that scale is built here.
+    pub fn bar(&self, code: &str, scale: &KLineScale) -> Option<&KlineItem> {
+        self.bars.get(&(code.to_string(), scale.clone()))
+    }
+
+    pub fn remove(&mut self, code: &str) {
+        self.last.remove(code);
+        self.bars.retain(|(c, _), _| c != code);
+    }
+}
+
+fn snapshot_time(data: &BaseData) -> Option<NaiveDateTime> {
+    let date = NaiveDate::parse_from_str(data.date.trim(), "%Y-%m-%d").ok()?;
+    let time = NaiveTime::parse_from_str(data.time.trim(), "%H:%M:%S").ok()?;
+    Some(date.and_time(time))
+}
+
+/// The end of the `minutes` long bar holding `at`, the way providers label
+/// bars. The call auction goes into the first bar of the morning, the lunch
+/// break and the close into the last bar before them.
+fn bucket(at: NaiveDateTime, minutes: i64) -> NaiveDateTime {
+    let date = at.date();
+    let hm = |(h, m): (u32, u32)| date.and_hms_opt(h, m, 0).unwrap_or_default();
+    let (open, close) = SESSIONS
+        .iter()
+        .rev()
+        .map(|&(open, close)| (hm(open), hm(close)))
+        .find(|&(open, _)| at >= open)
+        .unwrap_or((hm(SESSIONS[0].0), hm(SESSIONS[0].1)));
+    let elapsed = (at.max(open) - open).num_seconds();
+    let width = minutes.max(1) * 60;
+    let bars = ((elapsed + width - 1) / width).max(1);
+    (open + TimeDelta::seconds(bars * width)).min(close)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn at(time: &str) -> NaiveDateTime {
+        NaiveDateTime::parse_from_str(&format!("2024-09-25 {}", time), "%Y-%m-%d %H:%M:%S").unwrap()
+    }
+
+    fn quote(date: &str, time: &str, new: f32, vol: u64) -> BaseData {
+        BaseData {
+            new,
+            vol,
+            amount: vol as f32 * 10.0,
+            date: date.to_string(),
+            time: time.to_string(),
+            ..BaseData::default()
+        }
+    }
+
+    #[test]
+    fn buckets_by_scale_and_session() {
+        let cases = [
+            // the call auction goes into the first bar
+            ("09:25:00", 1, "09:31:00"),
+            ("09:30:00", 1, "09:31:00"),
+            ("09:31:00", 1, "09:31:00"),
+            ("09:31:01", 1, "09:32:00"),
+            ("09:34:59", 5, "09:35:00"),
+            ("09:35:00", 5, "09:35:00"),
+            ("09:35:01", 5, "09:40:00"),
+            ("09:45:01", 15, "10:00:00"),
+            // late lunch break ticks close the morning
+            ("11:30:02", 1, "11:30:00"),
+            ("12:10:00", 15, "11:30:00"),
+            ("13:00:00", 1, "13:01:00"),
+            ("13:00:00", 15, "13:15:00"),
+            ("14:59:30", 5, "15:00:00"),
+            ("15:00:03", 15, "15:00:00"),
+        ];
+        for (time, minutes, end) in cases {
+            assert_eq!(bucket(at(time), minutes), at(end), "{} {}m", time, minutes);
+        }
+    }
+
+    #[test]
+    fn ticks_fold_into_bars() {
+        let mut builder = CandleBuilder::default();
+        let day = "2024-09-25";
+        assert!(builder.update("sh600000", &quote(day, "09:30:05", 10.0, 1000)));
+        assert!(builder.update("sh600000", &quote(day, "09:30:30", 10.5, 1500)));
+        // repeated snapshot
+        assert!(!builder.update("sh600000", &quote(day, "09:30:30", 10.6, 1600)));
+        assert!(builder.update("sh600000", &quote(day, "09:30:50", 9.75, 1800)));
+
+        let bar = builder.bar("sh600000", &KLineScale::Intraday).unwrap();
+        assert_eq!(bar.day, at("09:31:00"));
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (10.0, 10.5, 9.75, 9.75)
+        );
+        // the first snapshot has nothing to measure against
+        assert_eq!(bar.volume, 800.0);
+        assert_eq!(bar.amount, 8000.0);
+
+        // the minute rolls over, the five minute bar carries on
+        assert!(builder.update("sh600000", &quote(day, "09:31:10", 10.25, 2000)));
+        let bar = builder.bar("sh600000", &KLineScale::Intraday).unwrap();
+        assert_eq!(bar.day, at("09:32:00"));
+        assert_eq!((bar.open, bar.close, bar.volume), (10.25, 10.25, 200.0));
+        let bar = builder.bar("sh600000", &KLineScale::Munute5).unwrap();
+        assert_eq!(bar.day, at("09:35:00"));
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (10.0, 10.5, 9.75, 10.25)
+        );
+        assert_eq!(bar.volume, 1000.0);
+        assert!(builder.bar("sh600000", &KLineScale::Day).is_none());
+    }
+
+    #[test]
+    fn lunch_break_rolls_into_the_afternoon() {
+        let mut builder = CandleBuilder::default();
+        let day = "2024-09-25";
+        builder.update("sh600000", &quote(day, "11:29:50", 10.0, 1000));
+        builder.update("sh600000", &quote(day, "11:30:02", 10.125, 1200));
+        let bar = builder.bar("sh600000", &KLineScale::Munute15).unwrap();
+        assert_eq!(
+            (bar.day, bar.close, bar.volume),
+            (at("11:30:00"), 10.125, 200.0)
+        );
+
+        builder.update("sh600000", &quote(day, "13:00:05", 10.375, 1500));
+        let bar = builder.bar("sh600000", &KLineScale::Munute15).unwrap();
+        assert_eq!(
+            (bar.day, bar.open, bar.volume),
+            (at("13:15:00"), 10.375, 300.0)
+        );
+    }
+
+    #[test]
+    fn volume_restarts_each_day() {
+        let mut builder = CandleBuilder::default();
+        builder.update("sh600000", &quote("2024-09-25", "14:59:50", 10.0, 90000));
+        builder.update("sh600000", &quote("2024-09-26", "09:30:05", 10.5, 1000));
+        let bar = builder.bar("sh600000", &KLineScale::Intraday).unwrap();
+        assert_eq!(bar.volume, 0.0);
+        // a stale snapshot from the day before is ignored
+        assert!(!builder.update("sh600000", &quote("2024-09-25", "15:00:00", 10.0, 90100)));
+        // no price yet, nothing to draw
+        assert!(!builder.update("sh600001", &quote("2024-09-26", "09:30:05", 0.0, 0)));
+        assert!(builder.bar("sh600001", &KLineScale::Intraday).is_none());
+    }
+}
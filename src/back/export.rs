@@ -0,0 +1,353 @@
+//! Quotes and K-lines out of the app as CSV, JSON Lines or Parquet.
+//!
+//! Both kinds of data are first laid out as a flat table, so every format
+//! gets the same columns: one per `BaseData` field, the order book as
+//! `bid1_vol`, `bid1_price` ... `ask5_price`, and futures fields left
+//! empty for other instruments.
+
+use std::{
+    fmt::{self, Display},
+    fs,
+    io::{self, Write},
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+
+use chrono::NaiveDate;
+use parquet::{
+    data_type::{ByteArray, ByteArrayType, DoubleType},
+    errors::ParquetError,
+    file::{properties::WriterProperties, writer::SerializedFileWriter},
+    schema::parser::parse_message_type,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use super::provider::Quote;
+use super::stock::{BaseData, FuturesData, Instrument, KLineScale, KlineItem};
+
+/// Order book levels written per side.
+const DEPTH: usize = 5;
+
+/// Quote prices are `f32`; go through their shortest decimal form so 10.4
+/// is written as 10.4 rather than 10.399999618530273.
+fn widen(x: f32) -> f64 {
+    x.to_string().parse().unwrap_or(x as f64)
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExportFormat {
+    #[default]
+    Csv,
+    JsonLines,
+    Parquet,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::Csv,
+        ExportFormat::JsonLines,
+        ExportFormat::Parquet,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Parquet => "Parquet",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::JsonLines => "jsonl",
+            ExportFormat::Parquet => "parquet",
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub enum ExportWhat {
+    /// A fresh snapshot of the watchlist.
+    Quotes,
+    /// Bars of `code` at `scale` between two days, both included.
+    Klines {
+        code: String,
+        scale: KLineScale,
+        from: Option<NaiveDate>,
+        to: Option<NaiveDate>,
+    },
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportRequest {
+    pub what: ExportWhat,
+    pub format: ExportFormat,
+    pub path: PathBuf,
+}
+
+#[derive(Debug)]
+pub enum ExportError {
+    Io(io::Error),
+    Csv(csv::Error),
+    Parquet(ParquetError),
+    /// Nothing to write, e.g. the fetch failed.
+    NoData(String),
+}
+
+impl Display for ExportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ExportError::Io(e) => write!(f, "io error: {}", e),
+            ExportError::Csv(e) => write!(f, "csv error: {}", e),
+            ExportError::Parquet(e) => write!(f, "parquet error: {}", e),
+            ExportError::NoData(what) => write!(f, "no data: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for ExportError {}
+
+impl From<io::Error> for ExportError {
+    fn from(e: io::Error) -> Self {
+        ExportError::Io(e)
+    }
+}
+
+impl From<csv::Error> for ExportError {
+    fn from(e: csv::Error) -> Self {
+        ExportError::Csv(e)
+    }
+}
+
+impl From<ParquetError> for ExportError {
+    fn from(e: ParquetError) -> Self {
+        ExportError::Parquet(e)
+    }
+}
+
+enum Column {
+    Text(Vec<String>),
+    Number(Vec<Option<f64>>),
+}
+
+impl Column {
+    fn cell(&self, row: usize) -> Value {
+        match self {
+            Column::Text(x) => Value::from(x[row].as_str()),
+            Column::Number(x) => x[row].map_or(Value::Null, Value::from),
+        }
+    }
+
+    fn csv_cell(&self, row: usize) -> String {
+        match self {
+            Column::Text(x) => x[row].clone(),
+            Column::Number(x) => x[row].map(|v| v.to_string()).unwrap_or_default(),
+        }
+    }
+}
+
+struct Table {
+    rows: usize,
+    columns: Vec<(String, Column)>,
+}
+
+impl Table {
+    fn new(rows: usize) -> Self {
+        Self {
+            rows,
+            columns: vec![],
+        }
+    }
+
+    fn text(mut self, name: &str, values: impl Iterator<Item = String>) -> Self {
+        self.columns
+            .push((name.to_string(), Column::Text(values.collect())));
+        self
+    }
+
+    fn number(mut self, name: &str, values: impl Iterator<Item = Option<f64>>) -> Self {
+        self.columns
+            .push((name.to_string(), Column::Number(values.collect())));
+        self
+    }
+
+    fn write(&self, path: &Path, format: ExportFormat) -> Result<(), ExportError> {
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        match format {
+            ExportFormat::Csv => self.write_csv(path),
+            ExportFormat::JsonLines => self.write_json_lines(path),
+            ExportFormat::Parquet => self.write_parquet(path),
+        }
+    }
+
+    fn write_csv(&self, path: &Path) -> Result<(), ExportError> {
+        let mut out = csv::Writer::from_path(path)?;
+        out.write_record(self.columns.iter().map(|(name, _)| name))?;
+        for row in 0..self.rows {
+            out.write_record(self.columns.iter().map(|(_, c)| c.csv_cell(row)))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn write_json_lines(&self, path: &Path) -> Result<(), ExportError> {
+        let mut out = io::BufWriter::new(fs::File::create(path)?);
+        for row in 0..self.rows {
+            // keep the column order, which a `Map` would sort
+            let fields = self
+                .columns
+                .iter()
+                .map(|(name, c)| format!("{}:{}", Value::from(name.as_str()), c.cell(row)))
+                .collect::<Vec<String>>();
+            writeln!(out, "{{{}}}", fields.join(","))?;
+        }
+        out.flush()?;
+        Ok(())
+    }
+
+    fn write_parquet(&self, path: &Path) -> Result<(), ExportError> {
+        let fields = self
+            .columns
+            .iter()
+            .map(|(name, c)| match c {
+                Column::Text(_) => format!("REQUIRED BYTE_ARRAY {} (STRING);", name),
+                Column::Number(_) => format!("OPTIONAL DOUBLE {};", name),
+            })
+            .collect::<Vec<String>>()
+            .join(" ");
+        let schema = Arc::new(parse_message_type(&format!(
+            "message export {{ {} }}",
+            fields
+        ))?);
+        let props = Arc::new(WriterProperties::builder().build());
+        let mut writer = SerializedFileWriter::new(fs::File::create(path)?, schema, props)?;
+        let mut group = writer.next_row_group()?;
+        let mut columns = self.columns.iter();
+        while let Some(mut out) = group.next_column()? {
+            match columns.next() {
+                Some((_, Column::Text(values))) => {
+                    let values = values
+                        .iter()
+                        .map(|x| ByteArray::from(x.as_str()))
+                        .collect::<Vec<ByteArray>>();
+                    out.typed::<ByteArrayType>()
+                        .write_batch(&values, None, None)?;
+                }
+                Some((_, Column::Number(values))) => {
+                    let present = values.iter().flatten().copied().collect::<Vec<f64>>();
+                    let levels = values
+                        .iter()
+                        .map(|x| x.is_some() as i16)
+                        .collect::<Vec<i16>>();
+                    out.typed::<DoubleType>()
+                        .write_batch(&present, Some(&levels), None)?;
+                }
+                None => {}
+            }
+            out.close()?;
+        }
+        group.close()?;
+        writer.close()?;
+        Ok(())
+    }
+}
+
+fn quote_table(quotes: &[Quote]) -> Table {
+    let data = || quotes.iter().map(|(_, _, d)| d);
+    let price = |f: fn(&BaseData) -> f32| data().map(move |d| Some(widen(f(d))));
+    let futures = |f: fn(&FuturesData) -> f64| {
+        data().map(move |d| match &d.instrument {
+            Instrument::Futures(x) => Some(f(x)),
+            Instrument::Equity => None,
+        })
+    };
+    let mut table = Table::new(quotes.len())
+        .text("code", quotes.iter().map(|(code, _, _)| code.clone()))
+        .text("name", quotes.iter().map(|(_, name, _)| name.clone()))
+        .text("date", data().map(|d| d.date.clone()))
+        .text("time", data().map(|d| d.time.clone()))
+        .text("currency", data().map(|d| d.currency.code().to_string()))
+        .number("open", price(|d| d.opening))
+        .number("prev_close", price(|d| d.closing))
+        .number("high", price(|d| d.hight))
+        .number("low", price(|d| d.low))
+        .number("price", price(|d| d.new))
+        .number("rise_per", price(|d| d.rise_per))
+        .number("bid", price(|d| d.bid))
+        .number("ask", price(|d| d.ask))
+        .number("volume", data().map(|d| Some(d.vol as f64)))
+        .number("amount", price(|d| d.amount));
+    let bids = data().map(|d| d.bids.as_slice()).collect::<Vec<_>>();
+    let asks = data().map(|d| d.asks.as_slice()).collect::<Vec<_>>();
+    for (side, book) in [("bid", &bids), ("ask", &asks)] {
+        let level = |i: usize| book.iter().map(move |levels| levels.get(i).copied());
+        for i in 0..DEPTH {
+            table = table
+                .number(
+                    &format!("{}{}_vol", side, i + 1),
+                    level(i).map(|x| x.map(|(vol, _)| vol as f64)),
+                )
+                .number(
+                    &format!("{}{}_price", side, i + 1),
+                    level(i).map(|x| x.map(|(_, price)| widen(price))),
+                );
+        }
+    }
+    table
+        .number("open_interest", futures(|x| x.open_interest))
+        .number("settlement", futures(|x| widen(x.settlement)))
+        .number("pre_settlement", futures(|x| widen(x.pre_settlement)))
+}
+
+fn kline_table(code: &str, scale: &KLineScale, klines: &[KlineItem]) -> Table {
+    let bar = |f: fn(&KlineItem) -> f64| klines.iter().map(move |x| Some(f(x)));
+    Table::new(klines.len())
+        .text("code", klines.iter().map(|_| code.to_string()))
+        .text("scale", klines.iter().map(|_| scale.label().to_string()))
+        .text(
+            "day",
+            klines
+                .iter()
+                .map(|x| x.day.format("%Y-%m-%d %H:%M:%S").to_string()),
+        )
+        .number("open", bar(|x| x.open))
+        .number("high", bar(|x| x.high))
+        .number("low", bar(|x| x.low))
+        .number("close", bar(|x| x.close))
+        .number("volume", bar(|x| x.volume))
+        .number("amount", bar(|x| x.amount))
+}
+
+/// Write a quote snapshot; returns the number of rows.
+pub fn export_quotes(
+    path: &Path,
+    format: ExportFormat,
+    quotes: &[Quote],
+) -> Result<usize, ExportError> {
+    quote_table(quotes).write(path, format)?;
+    Ok(quotes.len())
+}
+
+/// Write the bars of `klines` that fall in `from..=to`; returns how many.
+pub fn export_klines(
+    path: &Path,
+    format: ExportFormat,
+    code: &str,
+    scale: &KLineScale,
+    klines: &[KlineItem],
+    from: Option<NaiveDate>,
+    to: Option<NaiveDate>,
+) -> Result<usize, ExportError> {
+    let klines = klines
+        .iter()
+        .filter(|x| from.is_none_or(|d| x.day.date() >= d))
+        .filter(|x| to.is_none_or(|d| x.day.date() <= d))
+        .cloned()
+        .collect::<Vec<KlineItem>>();
+    kline_table(code, scale, &klines).write(path, format)?;
+    Ok(klines.len())
+}
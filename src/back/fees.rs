@@ -0,0 +1,121 @@
+use serde::{Deserialize, Serialize};
+
+use super::security::{Board, Exchange, SecurityId};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    Buy,
+    Sell,
+}
+
+/// Commission, stamp duty and transfer fee for A-share trades.
+///
+/// Rates are fractions of the traded amount, `0.00025` is 0.025%.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct FeeSchedule {
+    pub commission_rate: f64,
+    pub min_commission: f64,
+    /// Charged on sells only.
+    pub stamp_duty_rate: f64,
+    /// Shanghai listings only, both sides.
+    pub transfer_fee_rate: f64,
+    /// ETFs and other funds: no stamp duty or transfer fee.
+    pub fund_commission_rate: f64,
+    pub fund_min_commission: f64,
+    /// Bonds and convertibles: no stamp duty or transfer fee.
+    pub bond_commission_rate: f64,
+    pub bond_min_commission: f64,
+}
+
+impl Default for FeeSchedule {
+    fn default() -> Self {
+        Self {
+            commission_rate: 0.00025,
+            min_commission: 5.0,
+            stamp_duty_rate: 0.0005,
+            transfer_fee_rate: 0.00001,
+            fund_commission_rate: 0.0001,
+            fund_min_commission: 0.2,
+            bond_commission_rate: 0.00005,
+            bond_min_commission: 0.1,
+        }
+    }
+}
+
+/// The three parts of one order's fee.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Fee {
+    pub commission: f64,
+    pub stamp_duty: f64,
+    pub transfer: f64,
+}
+
+impl Fee {
+    pub fn total(&self) -> f64 {
+        self.commission + self.stamp_duty + self.transfer
+    }
+}
+
+impl FeeSchedule {
+    /// Commission rate and minimum for the board.
+    fn commission(&self, board: Board) -> (f64, f64) {
+        match board {
+            Board::Fund => (self.fund_commission_rate, self.fund_min_commission),
+            Board::Bond => (self.bond_commission_rate, self.bond_min_commission),
+            _ => (self.commission_rate, self.min_commission),
+        }
+    }
+
+    /// Ad valorem rates other than commission.
+    fn duties(&self, id: &SecurityId, side: Side) -> (f64, f64) {
+        if matches!(id.board, Board::Fund | Board::Bond | Board::Index) {
+            return (0.0, 0.0);
+        }
+        let stamp = match side {
+            Side::Sell => self.stamp_duty_rate,
+            Side::Buy => 0.0,
+        };
+        let transfer = match id.exchange {
+            Exchange::Sh => self.transfer_fee_rate,
+            _ => 0.0,
+        };
+        (stamp, transfer)
+    }
+
+    /// Fee for trading `amount` worth of `id`; zero outside the A-share
+    /// markets, whose fees vary too much by broker to guess.
+    pub fn fee(&self, id: &SecurityId, side: Side, amount: f64) -> Fee {
+        if !id.is_a_share() || amount <= 0.0 {
+            return Fee::default();
+        }
+        let (rate, min) = self.commission(id.board);
+        let (stamp, transfer) = self.duties(id, side);
+        Fee {
+            commission: (amount * rate).max(min),
+            stamp_duty: amount * stamp,
+            transfer: amount * transfer,
+        }
+    }
+
+    /// The price at which selling `quantity` recovers `cost`, the full
+    /// cost basis including buy fees.
+    pub fn break_even(&self, id: &SecurityId, quantity: f64, cost: f64) -> f64 {
+        if quantity <= 0.0 {
+            return 0.0;
+        }
+        if !id.is_a_share() {
+            return cost / quantity;
+        }
+        let (rate, min) = self.commission(id.board);
+        let (stamp, transfer) = self.duties(id, Side::Sell);
+        let duties = stamp + transfer;
+        let amount = cost / (1.0 - rate - duties);
+        if amount * rate >= min {
+            amount / quantity
+        } else {
+            // small orders pay the minimum commission
+            (cost + min) / (1.0 - duties) / quantity
+        }
+    }
+}
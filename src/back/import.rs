@@ -0,0 +1,305 @@
+//! OHLCV bars from CSV files, for instruments no provider serves.
+
+use std::{
+    fmt::{self, Display},
+    path::Path,
+};
+
+use chrono::{NaiveDate, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+use super::provider::Parsed;
+use super::stock::{BaseData, KlineItem, KlineItemD, QuoteParseError, Vol};
+
+/// Prefix of the code given to imported instruments, which no provider
+/// will be asked about.
+pub const IMPORTED_PREFIX: &str = "csv:";
+
+/// Which CSV column holds each `KlineItemD` field.
+///
+/// A column is named by its header, compared ignoring case, or by its
+/// 1-based position. `volume` and `amount` may be left empty.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct CsvMapping {
+    pub day: String,
+    pub open: String,
+    pub high: String,
+    pub low: String,
+    pub close: String,
+    pub volume: String,
+    pub amount: String,
+    /// chrono format of `day`, e.g. `%Y/%m/%d` or `%Y%m%d %H%M`; empty
+    /// accepts `2024-09-25` and `2024-09-25 10:45:00`.
+    pub day_format: String,
+    pub delimiter: char,
+    pub has_header: bool,
+}
+
+impl Default for CsvMapping {
+    fn default() -> Self {
+        Self {
+            day: "day".to_string(),
+            open: "open".to_string(),
+            high: "high".to_string(),
+            low: "low".to_string(),
+            close: "close".to_string(),
+            volume: "volume".to_string(),
+            amount: "amount".to_string(),
+            day_format: String::new(),
+            delimiter: ',',
+            has_header: true,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum ImportError {
+    Csv(csv::Error),
+    /// A mapped column is not in the file.
+    Column(String),
+    NoBars,
+}
+
+impl Display for ImportError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ImportError::Csv(e) => write!(f, "csv error: {}", e),
+            ImportError::Column(name) => write!(f, "no column {}", name),
+            ImportError::NoBars => write!(f, "no bars in file"),
+        }
+    }
+}
+
+impl std::error::Error for ImportError {}
+
+impl From<csv::Error> for ImportError {
+    fn from(e: csv::Error) -> Self {
+        ImportError::Csv(e)
+    }
+}
+
+/// Where `name` sits in `header`, or at its 1-based position.
+fn column(header: &[String], name: &str) -> Result<usize, ImportError> {
+    let name = name.trim();
+    header
+        .iter()
+        .position(|h| h.trim().eq_ignore_ascii_case(name))
+        .or_else(|| name.parse::<usize>().ok().filter(|&i| i > 0).map(|i| i - 1))
+        .ok_or_else(|| ImportError::Column(name.to_string()))
+}
+
+impl CsvMapping {
+    fn day(&self, raw: &str) -> Result<String, QuoteParseError> {
+        let format = self.day_format.trim();
+        if format.is_empty() {
+            return Ok(raw.to_string());
+        }
+        NaiveDateTime::parse_from_str(raw.trim(), format)
+            .or_else(|_| NaiveDate::parse_from_str(raw.trim(), format).map(NaiveDateTime::from))
+            .map(|day| day.format("%Y-%m-%d %H:%M:%S").to_string())
+            .map_err(|_| QuoteParseError::Field {
+                field: "day",
+                raw: raw.to_string(),
+            })
+    }
+}
+
+/// Read bars from `path`, oldest first. Rows that do not decode are
+/// returned as errors; a later row for the same day replaces an earlier one.
+pub fn import_csv(path: &Path, mapping: &CsvMapping) -> Result<Parsed<KlineItem>, ImportError> {
+    let mut reader = csv::ReaderBuilder::new()
+        .delimiter(mapping.delimiter as u8)
+        .has_headers(mapping.has_header)
+        .flexible(true)
+        .trim(csv::Trim::All)
+        .from_path(path)?;
+    let header = if mapping.has_header {
+        reader.headers()?.iter().map(str::to_string).collect()
+    } else {
+        vec![]
+    };
+    let optional = |name: &str| match name.trim() {
+        "" => Ok(None),
+        name => column(&header, name).map(Some),
+    };
+    let day = column(&header, &mapping.day)?;
+    let open = column(&header, &mapping.open)?;
+    let high = column(&header, &mapping.high)?;
+    let low = column(&header, &mapping.low)?;
+    let close = column(&header, &mapping.close)?;
+    let volume = optional(&mapping.volume)?;
+    let amount = optional(&mapping.amount)?;
+
+    let mut parsed = reader
+        .records()
+        .map(|record| {
+            let record = record.map_err(|e| QuoteParseError::Layout { raw: e.to_string() })?;
+            let field = |i: usize| {
+                record
+                    .get(i)
+                    .map(str::to_string)
+                    .ok_or_else(|| QuoteParseError::Layout {
+                        raw: record.iter().collect::<Vec<&str>>().join(","),
+                    })
+            };
+            KlineItem::try_from(KlineItemD {
+                day: mapping.day(&field(day)?)?,
+                open: field(open)?,
+                high: field(high)?,
+                low: field(low)?,
+                close: field(close)?,
+                volume: volume.map_or(Ok("0".to_string()), field)?,
+                amount: amount.map(field).transpose()?,
+            })
+        })
+        .collect::<Parsed<KlineItem>>();
+    if parsed.items.is_empty() {
+        return Err(ImportError::NoBars);
+    }
+    parsed.items.sort_by_key(|x| x.day);
+    parsed.items.dedup_by(|later, earlier| {
+        let same = later.day == earlier.day;
+        if same {
+            std::mem::swap(later, earlier);
+        }
+        same
+    });
+    Ok(parsed)
+}
+
+/// A quote standing in for the last imported bar, so the grid has
+/// something to show.
+pub fn snapshot(klines: &[KlineItem]) -> BaseData {
+    let Some(last) = klines.last() else {
+        return BaseData::default();
+    };
+    let prev_close = match klines.len() {
+        1 => last.open,
+        n => klines[n - 2].close,
+    };
+    BaseData {
+        date: last.day.format("%Y-%m-%d").to_string(),
+        time: last.day.format("%H:%M:%S").to_string(),
+        opening: last.open as f32,
+        closing: prev_close as f32,
+        hight: last.high as f32,
+        low: last.low as f32,
+        vol: last.volume as Vol,
+        amount: last.amount as f32,
+        new: last.close as f32,
+        rise_per: if prev_close > 0.0 {
+            (((last.close - prev_close) / prev_close * 10000.0).round() / 100.0) as f32
+        } else {
+            0.0
+        },
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, path::PathBuf, process};
+
+    use super::*;
+
+    /// Write `text` to a temp file named after the test.
+    fn file(name: &str, text: &str) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("import-{}-{}.csv", name, process::id()));
+        fs::write(&path, text).unwrap();
+        path
+    }
+
+    fn import(name: &str, text: &str, mapping: &CsvMapping) -> Parsed<KlineItem> {
+        let path = file(name, text);
+        let parsed = import_csv(&path, mapping);
+        let _ = fs::remove_file(&path);
+        parsed.unwrap()
+    }
+
+    fn days(bars: &[KlineItem]) -> Vec<String> {
+        bars.iter()
+            .map(|x| x.day.format("%Y-%m-%d").to_string())
+            .collect()
+    }
+
+    #[test]
+    fn reads_headerless_file_by_position() {
+        let mapping = CsvMapping {
+            day: "1".to_string(),
+            open: "2".to_string(),
+            high: "3".to_string(),
+            low: "4".to_string(),
+            close: "5".to_string(),
+            volume: "6".to_string(),
+            amount: String::new(),
+            delimiter: ';',
+            has_header: false,
+            ..CsvMapping::default()
+        };
+        let text = "2024-09-25;10;11;9.5;10.5;1000\n2024-09-26;10.5;12;10;11.5;2000\n";
+        let parsed = import("headerless", text, &mapping);
+        assert!(parsed.errors.is_empty());
+        assert_eq!(days(&parsed.items), ["2024-09-25", "2024-09-26"]);
+        let bar = &parsed.items[1];
+        assert_eq!(
+            (bar.open, bar.high, bar.low, bar.close),
+            (10.5, 12.0, 10.0, 11.5)
+        );
+        assert_eq!((bar.volume, bar.amount), (2000.0, 0.0));
+    }
+
+    #[test]
+    fn maps_named_headers_and_day_format() {
+        let mapping = CsvMapping {
+            day: "Date".to_string(),
+            open: "Open".to_string(),
+            high: "High".to_string(),
+            low: "Low".to_string(),
+            close: "Adj Close".to_string(),
+            volume: "Volume".to_string(),
+            amount: String::new(),
+            day_format: "%Y/%m/%d".to_string(),
+            ..CsvMapping::default()
+        };
+        let text = "Date,Open,High,Low,Close,Adj Close,Volume\n\
+            2024/09/26,10.5,12,10,11.5,11.25,2000\n\
+            2024/09/25,10,11,9.5,10.5,10.25,1000\n\
+            2024/09/27,x,12,10,11.5,11.25,2000\n";
+        let parsed = import("headers", text, &mapping);
+        // sorted oldest first, the bad row reported
+        assert_eq!(days(&parsed.items), ["2024-09-25", "2024-09-26"]);
+        assert_eq!(parsed.items[0].close, 10.25);
+        assert_eq!(parsed.errors.len(), 1);
+
+        let missing = CsvMapping {
+            close: "Last".to_string(),
+            ..mapping
+        };
+        let path = file("missing", text);
+        let result = import_csv(&path, &missing);
+        let _ = fs::remove_file(&path);
+        assert!(matches!(result, Err(ImportError::Column(name)) if name == "Last"));
+    }
+
+    #[test]
+    fn later_row_wins_for_a_day() {
+        let text = "day,open,high,low,close,volume,amount\n\
+            2024-09-25,10,11,9,10.5,1000,10000\n\
+            2024-09-26,10,11,9,10.5,1000,10000\n\
+            2024-09-25,10,11,9,10.75,1500,15000\n\
+            2024-09-25,10,11,9,11,1600,16000\n";
+        let parsed = import("duplicates", text, &CsvMapping::default());
+        assert_eq!(days(&parsed.items), ["2024-09-25", "2024-09-26"]);
+        let bar = &parsed.items[0];
+        assert_eq!((bar.close, bar.volume, bar.amount), (11.0, 1600.0, 16000.0));
+    }
+
+    #[test]
+    fn empty_file_has_no_bars() {
+        let path = file("empty", "day,open,high,low,close,volume,amount\n");
+        let result = import_csv(&path, &CsvMapping::default());
+        let _ = fs::remove_file(&path);
+        assert!(matches!(result, Err(ImportError::NoBars)));
+    }
+}
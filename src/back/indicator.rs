@@ -0,0 +1,690 @@
+//! Technical indicators over `KlineItem` series.
+//!
+//! Every indicator is a small state machine fed one bar at a time, so a
+//! series can be extended when a new bar arrives instead of recomputed.
+//! Smoothing follows the conventions of Chinese terminals: `SMA(X,N,1)` for
+//! RSI and KDJ, a plain average of true range for ATR, and MACD bars at
+//! twice the DIF/DEA spread.
+
+use std::collections::VecDeque;
+
+use chrono::NaiveDateTime;
+
+use super::stock::KlineItem;
+
+pub trait Indicator: Clone {
+    type Output: Clone;
+
+    /// Fold in the next bar and return the value at it.
+    fn next(&mut self, bar: &KlineItem) -> Self::Output;
+}
+
+/// Run `indicator` over the whole series.
+pub fn compute<I: Indicator>(mut indicator: I, items: &[KlineItem]) -> Vec<I::Output> {
+    items.iter().map(|bar| indicator.next(bar)).collect()
+}
+
+/// Simple moving average of closes; `None` until `period` bars were seen.
+#[derive(Clone, Debug)]
+pub struct Ma {
+    period: usize,
+    window: VecDeque<f64>,
+    sum: f64,
+}
+
+impl Ma {
+    pub fn new(period: usize) -> Self {
+        Self {
+            period: period.max(1),
+            window: VecDeque::new(),
+            sum: 0.0,
+        }
+    }
+
+    fn push(&mut self, value: f64) -> Option<f64> {
+        self.window.push_back(value);
+        self.sum += value;
+        if self.window.len() > self.period {
+            self.sum -= self.window.pop_front().unwrap_or_default();
+        }
+        (self.window.len() == self.period).then(|| self.sum / self.period as f64)
+    }
+}
+
+impl Indicator for Ma {
+    type Output = Option<f64>;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        self.push(bar.close)
+    }
+}
+
+/// Exponential moving average of closes, seeded with the first close.
+#[derive(Clone, Debug)]
+pub struct Ema {
+    alpha: f64,
+    value: Option<f64>,
+}
+
+impl Ema {
+    pub fn new(period: usize) -> Self {
+        Self {
+            alpha: 2.0 / (period.max(1) as f64 + 1.0),
+            value: None,
+        }
+    }
+
+    fn push(&mut self, x: f64) -> f64 {
+        let value = match self.value {
+            Some(prev) => prev + self.alpha * (x - prev),
+            None => x,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+impl Indicator for Ema {
+    type Output = f64;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        self.push(bar.close)
+    }
+}
+
+/// `SMA(X,N,M)`: `(M*X + (N-M)*prev) / N`, seeded with the first value.
+#[derive(Clone, Debug)]
+struct WeightedSma {
+    n: f64,
+    m: f64,
+    value: Option<f64>,
+}
+
+impl WeightedSma {
+    fn new(n: usize, m: usize) -> Self {
+        Self {
+            n: n.max(1) as f64,
+            m: m as f64,
+            value: None,
+        }
+    }
+
+    fn seeded(n: usize, m: usize, seed: f64) -> Self {
+        Self {
+            value: Some(seed),
+            ..Self::new(n, m)
+        }
+    }
+
+    fn push(&mut self, x: f64) -> f64 {
+        let value = match self.value {
+            Some(prev) => (self.m * x + (self.n - self.m) * prev) / self.n,
+            None => x,
+        };
+        self.value = Some(value);
+        value
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct MacdValue {
+    pub dif: f64,
+    pub dea: f64,
+    /// `2 * (dif - dea)`
+    pub hist: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Macd {
+    fast: Ema,
+    slow: Ema,
+    signal: Ema,
+}
+
+impl Macd {
+    pub fn new(fast: usize, slow: usize, signal: usize) -> Self {
+        Self {
+            fast: Ema::new(fast),
+            slow: Ema::new(slow),
+            signal: Ema::new(signal),
+        }
+    }
+}
+
+impl Default for Macd {
+    fn default() -> Self {
+        Self::new(12, 26, 9)
+    }
+}
+
+impl Indicator for Macd {
+    type Output = MacdValue;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        let dif = self.fast.push(bar.close) - self.slow.push(bar.close);
+        let dea = self.signal.push(dif);
+        MacdValue {
+            dif,
+            dea,
+            hist: 2.0 * (dif - dea),
+        }
+    }
+}
+
+/// Relative strength index; `None` on the first bar, which has no change.
+#[derive(Clone, Debug)]
+pub struct Rsi {
+    prev_close: Option<f64>,
+    gain: WeightedSma,
+    change: WeightedSma,
+}
+
+impl Rsi {
+    pub fn new(period: usize) -> Self {
+        Self {
+            prev_close: None,
+            gain: WeightedSma::new(period, 1),
+            change: WeightedSma::new(period, 1),
+        }
+    }
+}
+
+impl Indicator for Rsi {
+    type Output = Option<f64>;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        let prev = self.prev_close.replace(bar.close)?;
+        let diff = bar.close - prev;
+        let gain = self.gain.push(diff.max(0.0));
+        let change = self.change.push(diff.abs());
+        Some(if change > 0.0 {
+            gain / change * 100.0
+        } else {
+            50.0
+        })
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct KdjValue {
+    pub k: f64,
+    pub d: f64,
+    pub j: f64,
+}
+
+#[derive(Clone, Debug)]
+pub struct Kdj {
+    period: usize,
+    highs: VecDeque<f64>,
+    lows: VecDeque<f64>,
+    k: WeightedSma,
+    d: WeightedSma,
+}
+
+impl Kdj {
+    pub fn new(period: usize, k: usize, d: usize) -> Self {
+        Self {
+            period: period.max(1),
+            highs: VecDeque::new(),
+            lows: VecDeque::new(),
+            k: WeightedSma::seeded(k, 1, 50.0),
+            d: WeightedSma::seeded(d, 1, 50.0),
+        }
+    }
+}
+
+impl Default for Kdj {
+    fn default() -> Self {
+        Self::new(9, 3, 3)
+    }
+}
+
+impl Indicator for Kdj {
+    type Output = KdjValue;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        self.highs.push_back(bar.high);
+        self.lows.push_back(bar.low);
+        if self.highs.len() > self.period {
+            self.highs.pop_front();
+            self.lows.pop_front();
+        }
+        let high = self.highs.iter().copied().fold(f64::MIN, f64::max);
+        let low = self.lows.iter().copied().fold(f64::MAX, f64::min);
+        let rsv = if high > low {
+            (bar.close - low) / (high - low) * 100.0
+        } else {
+            50.0
+        };
+        let k = self.k.push(rsv);
+        let d = self.d.push(k);
+        KdjValue {
+            k,
+            d,
+            j: 3.0 * k - 2.0 * d,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct BollValue {
+    pub upper: f64,
+    pub mid: f64,
+    pub lower: f64,
+}
+
+/// Bollinger bands at `width` population standard deviations.
+#[derive(Clone, Debug)]
+pub struct Boll {
+    ma: Ma,
+    width: f64,
+}
+
+impl Boll {
+    pub fn new(period: usize, width: f64) -> Self {
+        Self {
+            ma: Ma::new(period),
+            width,
+        }
+    }
+}
+
+impl Default for Boll {
+    fn default() -> Self {
+        Self::new(20, 2.0)
+    }
+}
+
+impl Indicator for Boll {
+    type Output = Option<BollValue>;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        let mid = self.ma.push(bar.close)?;
+        let n = self.ma.window.len() as f64;
+        let var = self
+            .ma
+            .window
+            .iter()
+            .map(|x| (x - mid).powi(2))
+            .sum::<f64>()
+            / n;
+        let band = self.width * var.sqrt();
+        Some(BollValue {
+            upper: mid + band,
+            mid,
+            lower: mid - band,
+        })
+    }
+}
+
+/// Average true range; `None` until `period` bars were seen.
+#[derive(Clone, Debug)]
+pub struct Atr {
+    prev_close: Option<f64>,
+    ma: Ma,
+}
+
+impl Atr {
+    pub fn new(period: usize) -> Self {
+        Self {
+            prev_close: None,
+            ma: Ma::new(period),
+        }
+    }
+}
+
+impl Indicator for Atr {
+    type Output = Option<f64>;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        let range = bar.high - bar.low;
+        let tr = match self.prev_close.replace(bar.close) {
+            Some(pc) => range.max((bar.high - pc).abs()).max((bar.low - pc).abs()),
+            None => range,
+        };
+        self.ma.push(tr)
+    }
+}
+
+/// On-balance volume, starting at zero.
+#[derive(Clone, Debug, Default)]
+pub struct Obv {
+    prev_close: Option<f64>,
+    value: f64,
+}
+
+impl Indicator for Obv {
+    type Output = f64;
+
+    fn next(&mut self, bar: &KlineItem) -> Self::Output {
+        if let Some(pc) = self.prev_close.replace(bar.close) {
+            if bar.close > pc {
+                self.value += bar.volume;
+            } else if bar.close < pc {
+                self.value -= bar.volume;
+            }
+        }
+        self.value
+    }
+}
+
+/// An indicator with its values, extended bar by bar.
+///
+/// The newest bar is usually still forming, so pushing a bar with the same
+/// `day` as the last one replaces it rather than appending.
+#[derive(Clone, Debug)]
+pub struct Series<I: Indicator> {
+    initial: I,
+    /// State after every bar but the last.
+    committed: I,
+    /// State after the last bar.
+    current: I,
+    values: Vec<I::Output>,
+    days: Vec<NaiveDateTime>,
+}
+
+impl<I: Indicator> Series<I> {
+    pub fn new(indicator: I) -> Self {
+        Self {
+            initial: indicator.clone(),
+            committed: indicator.clone(),
+            current: indicator,
+            values: vec![],
+            days: vec![],
+        }
+    }
+
+    pub fn values(&self) -> &[I::Output] {
+        &self.values
+    }
+
+    /// The values of the newest `n` bars.
+    pub fn tail(&self, n: usize) -> &[I::Output] {
+        &self.values[self.values.len().saturating_sub(n)..]
+    }
+
+    pub fn last(&self) -> Option<&I::Output> {
+        self.values.last()
+    }
+
+    pub fn push(&mut self, bar: &KlineItem) {
+        if self.days.last() == Some(&bar.day) {
+            self.current = self.committed.clone();
+            self.values.pop();
+        } else {
+            self.committed = self.current.clone();
+            self.days.push(bar.day);
+        }
+        self.values.push(self.current.next(bar));
+    }
+
+    /// Catch up with `items`, the bars this series was fed plus any newer
+    /// ones. The last bar seen is recomputed, since a forming bar keeps
+    /// changing, and newer bars are appended. When the first or last bar
+    /// seen is no longer where it was, e.g. after older history was put in
+    /// front, everything is recomputed. Call `reset` when bars before the
+    /// last one were replaced, or the scale changed.
+    pub fn sync(&mut self, items: &[KlineItem]) {
+        let seen = self.days.len();
+        let aligned = seen > 0
+            && items.len() >= seen
+            && items[0].day == self.days[0]
+            && items[seen - 1].day == self.days[seen - 1];
+        if !aligned {
+            self.reset();
+        }
+        let from = self.days.len().saturating_sub(1);
+        items[from..].iter().for_each(|bar| self.push(bar));
+    }
+
+    pub fn reset(&mut self) {
+        *self = Self::new(self.initial.clone());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate};
+
+    use super::*;
+
+    /// `(high, low, close, volume)` per day.
+    const BARS: [(f64, f64, f64, f64); 6] = [
+        (10.5, 9.5, 10.0, 100.0),
+        (11.2, 10.1, 11.0, 150.0),
+        (12.5, 10.8, 12.0, 120.0),
+        (12.1, 10.6, 11.0, 200.0),
+        (13.4, 11.0, 13.0, 180.0),
+        (13.2, 12.2, 12.5, 90.0),
+    ];
+
+    fn bars() -> Vec<KlineItem> {
+        let first = NaiveDate::from_ymd_opt(2024, 9, 23)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        BARS.iter()
+            .enumerate()
+            .map(|(i, &(high, low, close, volume))| KlineItem {
+                day: first + Duration::days(i as i64),
+                open: close,
+                high,
+                low,
+                close,
+                volume,
+                amount: 0.0,
+            })
+            .collect()
+    }
+
+    fn assert_close(actual: impl IntoIterator<Item = Option<f64>>, expected: &[Option<f64>]) {
+        let actual = actual.into_iter().collect::<Vec<Option<f64>>>();
+        assert_eq!(actual.len(), expected.len(), "{:?}", actual);
+        for (a, e) in actual.iter().zip(expected) {
+            match (a, e) {
+                (Some(a), Some(e)) => {
+                    assert!((a - e).abs() < 1e-9, "{:?} != {:?}", actual, expected)
+                }
+                (a, e) => assert_eq!(a, e, "{:?} != {:?}", actual, expected),
+            }
+        }
+    }
+
+    #[test]
+    fn ma() {
+        let ma = compute(Ma::new(3), &bars());
+        assert_close(
+            ma,
+            &[
+                None,
+                None,
+                Some(11.0),
+                Some(34.0 / 3.0),
+                Some(12.0),
+                Some(36.5 / 3.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn ema() {
+        let ema = compute(Ema::new(3), &bars());
+        assert_close(
+            ema.into_iter().map(Some),
+            &[10.0, 10.5, 11.25, 11.125, 12.0625, 12.28125].map(Some),
+        );
+    }
+
+    #[test]
+    fn macd() {
+        let macd = compute(Macd::new(3, 5, 2), &bars());
+        assert_close(
+            macd.iter().map(|m| Some(m.dif)),
+            &[
+                0.0,
+                0.166666666667,
+                0.361111111111,
+                0.199074074074,
+                0.445216049383,
+                0.369727366255,
+            ]
+            .map(Some),
+        );
+        assert_close(
+            macd.iter().map(|m| Some(m.dea)),
+            &[
+                0.0,
+                0.111111111111,
+                0.277777777778,
+                0.225308641975,
+                0.371913580247,
+                0.370456104252,
+            ]
+            .map(Some),
+        );
+        assert_close(
+            macd.iter().map(|m| Some(m.hist)),
+            &[
+                0.0,
+                0.111111111111,
+                0.166666666667,
+                -0.052469135802,
+                0.146604938272,
+                -0.001457475995,
+            ]
+            .map(Some),
+        );
+    }
+
+    #[test]
+    fn rsi() {
+        let rsi = compute(Rsi::new(3), &bars());
+        assert_close(
+            rsi,
+            &[
+                None,
+                Some(100.0),
+                Some(100.0),
+                Some(200.0 / 3.0),
+                Some(250.0 / 3.0),
+                Some(70.175438596491),
+            ],
+        );
+    }
+
+    #[test]
+    fn kdj() {
+        let kdj = compute(Kdj::new(3, 3, 3), &bars());
+        assert_close(
+            kdj.iter().map(|x| Some(x.k)),
+            &[
+                50.0,
+                62.745098039216,
+                69.607843137255,
+                58.90522875817,
+                67.841581076875,
+                67.846768336964,
+            ]
+            .map(Some),
+        );
+        assert_close(
+            kdj.iter().map(|x| Some(x.d)),
+            &[
+                50.0,
+                54.248366013072,
+                59.368191721133,
+                59.213870733479,
+                62.089774181277,
+                64.008772233173,
+            ]
+            .map(Some),
+        );
+        assert_close(
+            kdj.iter().map(|x| Some(x.j)),
+            &[
+                50.0,
+                79.738562091503,
+                90.087145969499,
+                58.287944807553,
+                79.345194868071,
+                75.522760544547,
+            ]
+            .map(Some),
+        );
+    }
+
+    #[test]
+    fn boll() {
+        let boll = compute(Boll::new(3, 2.0), &bars());
+        assert_close(
+            boll.iter().map(|b| b.map(|b| b.upper)),
+            &[
+                None,
+                None,
+                Some(12.632993161855),
+                Some(12.276142374915),
+                Some(13.632993161855),
+                Some(13.866339837864),
+            ],
+        );
+        assert_close(
+            boll.iter().map(|b| b.map(|b| b.lower)),
+            &[
+                None,
+                None,
+                Some(9.367006838145),
+                Some(10.390524291751),
+                Some(10.367006838145),
+                Some(10.466993495469),
+            ],
+        );
+    }
+
+    #[test]
+    fn atr() {
+        let atr = compute(Atr::new(3), &bars());
+        assert_close(
+            atr,
+            &[
+                None,
+                None,
+                Some(1.3),
+                Some(4.4 / 3.0),
+                Some(5.6 / 3.0),
+                Some(4.9 / 3.0),
+            ],
+        );
+    }
+
+    #[test]
+    fn obv() {
+        let obv = compute(Obv::default(), &bars());
+        assert_close(
+            obv.into_iter().map(Some),
+            &[0.0, 150.0, 270.0, 70.0, 250.0, 160.0].map(Some),
+        );
+    }
+
+    #[test]
+    fn series_appends_and_revises_last_bar() {
+        let mut bars = bars();
+        let mut series = Series::new(Macd::default());
+        series.sync(&bars[..4]);
+        series.sync(&bars);
+        assert_eq!(series.values(), compute(Macd::default(), &bars));
+
+        // the forming bar moves
+        bars[5].close = 14.0;
+        series.sync(&bars);
+        assert_eq!(series.values(), compute(Macd::default(), &bars));
+    }
+
+    #[test]
+    fn series_starts_over_when_history_is_prepended() {
+        let bars = bars();
+        let mut series = Series::new(Rsi::new(3));
+        series.sync(&bars[2..]);
+        series.sync(&bars);
+        assert_eq!(series.values(), compute(Rsi::new(3), &bars));
+    }
+}
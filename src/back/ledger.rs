@@ -0,0 +1,346 @@
+use std::collections::{BTreeMap, VecDeque};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+
+use super::portfolio::{Holding, Portfolio};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TxKind {
+    #[default]
+    Buy,
+    Sell,
+    /// `quantity` shares paid `price` each.
+    Dividend,
+    /// A charge not tied to a trade, `price` is the amount.
+    Fee,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub id: u64,
+    pub date: NaiveDate,
+    pub code: String,
+    pub kind: TxKind,
+    pub quantity: f64,
+    pub price: f64,
+    pub fee: f64,
+    #[serde(default)]
+    pub account: String,
+}
+
+impl Transaction {
+    pub fn amount(&self) -> f64 {
+        match self.kind {
+            TxKind::Fee => self.price,
+            _ => self.quantity * self.price,
+        }
+    }
+}
+
+/// How sells are matched against earlier buys.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum LotMethod {
+    #[default]
+    Fifo,
+    Lifo,
+    /// Every buy is pooled into one lot at the running average cost.
+    Average,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct Lot {
+    pub date: NaiveDate,
+    pub quantity: f64,
+    /// Cost per share, buy fees included.
+    pub unit_cost: f64,
+}
+
+/// Gain booked by a sell, dividend or fee.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Realized {
+    pub date: NaiveDate,
+    pub kind: TxKind,
+    pub quantity: f64,
+    pub proceeds: f64,
+    pub cost: f64,
+}
+
+impl Realized {
+    pub fn gain(&self) -> f64 {
+        self.proceeds - self.cost
+    }
+}
+
+/// What is left of a code after replaying its transactions.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Position {
+    pub lots: Vec<Lot>,
+    pub realized: Vec<Realized>,
+    pub account: String,
+}
+
+impl Position {
+    pub fn quantity(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity).sum()
+    }
+
+    /// Remaining cost basis.
+    pub fn cost(&self) -> f64 {
+        self.lots.iter().map(|l| l.quantity * l.unit_cost).sum()
+    }
+
+    pub fn avg_cost(&self) -> f64 {
+        let q = self.quantity();
+        if q > 0.0 {
+            self.cost() / q
+        } else {
+            0.0
+        }
+    }
+
+    pub fn realized_gain(&self) -> f64 {
+        self.realized.iter().map(Realized::gain).sum()
+    }
+
+    fn apply(&mut self, tx: &Transaction, method: LotMethod) {
+        if !tx.account.is_empty() {
+            self.account = tx.account.clone();
+        }
+        match tx.kind {
+            TxKind::Buy => {
+                if tx.quantity <= 0.0 {
+                    return;
+                }
+                let lot = Lot {
+                    date: tx.date,
+                    quantity: tx.quantity,
+                    unit_cost: (tx.amount() + tx.fee) / tx.quantity,
+                };
+                match (method, self.lots.first_mut()) {
+                    (LotMethod::Average, Some(pool)) => {
+                        let quantity = pool.quantity + lot.quantity;
+                        pool.unit_cost = (pool.quantity * pool.unit_cost
+                            + lot.quantity * lot.unit_cost)
+                            / quantity;
+                        pool.quantity = quantity;
+                    }
+                    _ => self.lots.push(lot),
+                }
+            }
+            TxKind::Sell => {
+                // short positions are not tracked, anything beyond what is
+                // held is ignored
+                let mut left = tx.quantity.min(self.quantity());
+                let sold = left;
+                let mut cost = 0.0;
+                let mut lots = self.lots.drain(..).collect::<VecDeque<Lot>>();
+                while left > f64::EPSILON {
+                    let lot = match method {
+                        LotMethod::Lifo => lots.back_mut(),
+                        _ => lots.front_mut(),
+                    };
+                    let Some(lot) = lot else {
+                        break;
+                    };
+                    let take = left.min(lot.quantity);
+                    cost += take * lot.unit_cost;
+                    lot.quantity -= take;
+                    left -= take;
+                    if lot.quantity <= f64::EPSILON {
+                        match method {
+                            LotMethod::Lifo => lots.pop_back(),
+                            _ => lots.pop_front(),
+                        };
+                    }
+                }
+                self.lots = lots.into();
+                // the fee is charged on the whole order even if part of it
+                // could not be matched
+                self.realized.push(Realized {
+                    date: tx.date,
+                    kind: tx.kind,
+                    quantity: sold,
+                    proceeds: sold * tx.price - tx.fee,
+                    cost,
+                });
+            }
+            TxKind::Dividend | TxKind::Fee => {
+                let (proceeds, cost) = match tx.kind {
+                    TxKind::Dividend => (tx.amount() - tx.fee, 0.0),
+                    _ => (0.0, tx.amount() + tx.fee),
+                };
+                self.realized.push(Realized {
+                    date: tx.date,
+                    kind: tx.kind,
+                    quantity: tx.quantity,
+                    proceeds,
+                    cost,
+                });
+            }
+        }
+    }
+}
+
+/// Every buy, sell, dividend and fee, persisted next to the app settings.
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Ledger {
+    pub method: LotMethod,
+    transactions: Vec<Transaction>,
+    next_id: u64,
+}
+
+impl Ledger {
+    pub fn transactions(&self) -> &[Transaction] {
+        &self.transactions
+    }
+
+    /// Store `tx` under a fresh id, keeping the list ordered by date.
+    pub fn add(&mut self, mut tx: Transaction) -> u64 {
+        self.next_id += 1;
+        tx.id = self.next_id;
+        let at = self.transactions.partition_point(|x| x.date <= tx.date);
+        self.transactions.insert(at, tx);
+        self.next_id
+    }
+
+    pub fn remove(&mut self, id: u64) {
+        self.transactions.retain(|x| x.id != id);
+    }
+
+    pub fn has_code(&self, code: &str) -> bool {
+        self.transactions.iter().any(|x| x.code == code)
+    }
+
+    pub fn position(&self, code: &str) -> Position {
+        let mut position = Position::default();
+        self.transactions
+            .iter()
+            .filter(|x| x.code == code)
+            .for_each(|tx| position.apply(tx, self.method));
+        position
+    }
+
+    pub fn positions(&self) -> BTreeMap<String, Position> {
+        let mut positions: BTreeMap<String, Position> = BTreeMap::new();
+        for tx in &self.transactions {
+            positions
+                .entry(tx.code.clone())
+                .or_default()
+                .apply(tx, self.method);
+        }
+        positions
+    }
+
+    /// Holdings of codes with transactions follow the ledger; the rest are
+    /// left as entered by hand.
+    pub fn apply_to(&self, portfolio: &mut Portfolio) {
+        for (code, position) in self.positions() {
+            portfolio.holdings.insert(
+                code,
+                Holding {
+                    quantity: position.quantity(),
+                    avg_cost: position.avg_cost(),
+                    account: position.account,
+                },
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(day: u32, kind: TxKind, quantity: f64, price: f64, fee: f64) -> Transaction {
+        Transaction {
+            id: 0,
+            date: NaiveDate::from_ymd_opt(2024, 9, day).unwrap(),
+            code: "sh600519".to_string(),
+            kind,
+            quantity,
+            price,
+            fee,
+            account: String::new(),
+        }
+    }
+
+    /// Buy 100 at 10 with a 10 fee, buy 200 at 12, then sell 150 at 15 with a
+    /// 5 fee.
+    fn position(method: LotMethod) -> Position {
+        let mut ledger = Ledger {
+            method,
+            ..Ledger::default()
+        };
+        ledger.add(tx(2, TxKind::Buy, 100.0, 10.0, 10.0));
+        ledger.add(tx(3, TxKind::Buy, 200.0, 12.0, 0.0));
+        ledger.add(tx(4, TxKind::Sell, 150.0, 15.0, 5.0));
+        ledger.position("sh600519")
+    }
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn fifo_sell_spans_lots() {
+        let p = position(LotMethod::Fifo);
+        // all of the first lot, fee included, and 50 of the second
+        assert_eq!(p.realized.len(), 1);
+        assert_close(p.realized[0].cost, 100.0 * 10.1 + 50.0 * 12.0);
+        assert_close(p.realized[0].proceeds, 150.0 * 15.0 - 5.0);
+        assert_close(p.realized_gain(), 635.0);
+        assert_eq!(p.lots.len(), 1);
+        assert_close(p.quantity(), 150.0);
+        assert_close(p.avg_cost(), 12.0);
+    }
+
+    #[test]
+    fn lifo_sells_newest_lot_first() {
+        let p = position(LotMethod::Lifo);
+        assert_close(p.realized[0].cost, 150.0 * 12.0);
+        assert_eq!(p.lots.len(), 2);
+        assert_close(p.lots[0].quantity, 100.0);
+        assert_close(p.lots[0].unit_cost, 10.1);
+        assert_close(p.lots[1].quantity, 50.0);
+        assert_close(p.cost(), 1010.0 + 600.0);
+    }
+
+    #[test]
+    fn average_pools_buys() {
+        let p = position(LotMethod::Average);
+        let avg = (1010.0 + 2400.0) / 300.0;
+        assert_eq!(p.lots.len(), 1);
+        assert_close(p.realized[0].cost, 150.0 * avg);
+        assert_close(p.quantity(), 150.0);
+        assert_close(p.avg_cost(), avg);
+    }
+
+    #[test]
+    fn selling_more_than_held_closes_the_position() {
+        let mut p = Position::default();
+        p.apply(&tx(2, TxKind::Buy, 100.0, 10.0, 0.0), LotMethod::Fifo);
+        p.apply(&tx(3, TxKind::Sell, 150.0, 12.0, 5.0), LotMethod::Fifo);
+        // only what was held is matched, the fee still counts in full
+        assert_close(p.realized[0].quantity, 100.0);
+        assert_close(p.realized[0].proceeds, 1200.0 - 5.0);
+        assert_close(p.realized[0].cost, 1000.0);
+        assert!(p.lots.is_empty());
+        assert_eq!(p.avg_cost(), 0.0);
+
+        p.apply(&tx(4, TxKind::Buy, 10.0, 11.0, 0.0), LotMethod::Fifo);
+        assert_close(p.quantity(), 10.0);
+        assert_close(p.avg_cost(), 11.0);
+    }
+
+    #[test]
+    fn dividends_and_fees_book_gains() {
+        let mut p = Position::default();
+        p.apply(&tx(2, TxKind::Buy, 100.0, 10.0, 0.0), LotMethod::Fifo);
+        p.apply(&tx(3, TxKind::Dividend, 100.0, 0.5, 2.0), LotMethod::Fifo);
+        p.apply(&tx(4, TxKind::Fee, 0.0, 8.0, 0.0), LotMethod::Fifo);
+        assert_close(p.realized_gain(), 48.0 - 8.0);
+        assert_close(p.quantity(), 100.0);
+    }
+}
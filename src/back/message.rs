@@ -1,17 +1,54 @@
-use super::stock::{BaseData, KLineScale, KlineItem, Stock};
+use std::path::PathBuf;
+
+use chrono::NaiveDateTime;
+
+use super::alert::{Alert, AlertRule};
+use super::export::ExportRequest;
+use super::provider::replay::{ReplayCommand, ReplayStatus};
+use super::security::SecurityId;
+use super::webhook::WebhookConfig;
+use super::stock::{BaseData, KLineScale, KlineItem, QuoteParseError};
 
 #[derive(Debug)]
 pub enum ToBackend {
     Refresh,
     SetInterval(u32),
-    StockAdd(String),
+    StockAdd(SecurityId),
     StockDel(String),
-    StockKLine(String, KLineScale)
+    StockKLine(String, KLineScale),
+    /// Fetch a page of bars older than the oldest loaded one, with how
+    /// many bars are loaded.
+    StockKLineHistory(String, KLineScale, NaiveDateTime, u32),
+    /// Bars per K-line request.
+    SetKlineBars(u32),
+    SetAlerts(Vec<AlertRule>),
+    /// Path of the sound played for alerts, empty for none.
+    SetAlertSound(String),
+    SetWebhook(WebhookConfig),
+    /// Write raw quote responses to disk.
+    SetRecording(bool),
+    /// Take quotes from a recording instead of the network.
+    StartReplay(PathBuf),
+    StopReplay,
+    Replay(ReplayCommand),
+    /// Write quotes or K-lines to a file.
+    Export(ExportRequest),
 }
 
 #[derive(Debug)]
 pub enum ToFrontend {
     DataList(Vec<(String,String,BaseData)>),
     Data(String,String,BaseData),
-    Kline(String,Vec<KlineItem>)
+    Kline(String, KLineScale, Vec<KlineItem>),
+    /// Answer to `StockKLineHistory`, empty if the fetch failed; true once
+    /// nothing older can be had.
+    KlineHistory(String, KLineScale, Vec<KlineItem>, bool),
+    /// The forming bar, built from quotes between kline fetches.
+    KlineBar(String, KLineScale, KlineItem),
+    ParseErrors(Vec<QuoteParseError>),
+    Alert(Alert),
+    /// Playback position while replaying, `None` once live again.
+    Replay(Option<ReplayStatus>),
+    /// The file written and its row count, or why it failed.
+    Exported(Result<(PathBuf, usize), String>),
 }
@@ -1,45 +1,123 @@
-use std::time::Duration;
+use std::{sync::Arc, time::Duration};
+
+use chrono::{NaiveDateTime, TimeDelta, Utc};
 
 use crossbeam::{
     channel::{tick, Receiver, Sender},
     select,
 };
+use alert::AlertEngine;
+use candle::{CandleBuilder, LOCAL_SCALES};
+use export::{ExportError, ExportRequest, ExportWhat};
+use notify::Notifier;
+use webhook::Webhook;
 use eframe::egui::ahash::HashMap;
-use stock::{KLineScale, Stock};
-use tracing::error;
+use provider::{record::Recorder, ProviderError, Quote, QuoteProvider, ReplayProvider};
+use security::SecurityId;
+use stock::{BaseData, KLineScale, KlineItem, QuoteParseError};
+use store::KlineStore;
+use tracing::{debug, error};
 
 pub mod message;
 use message::{ToBackend, ToFrontend};
 
-use self::stock::check_stock_code;
-
+pub mod alert;
+pub mod candle;
+pub mod export;
+pub mod fees;
+pub mod import;
+pub mod indicator;
+pub mod ledger;
+pub mod notify;
+pub mod portfolio;
+pub mod provider;
+pub mod security;
 pub mod stock;
+pub mod store;
+pub mod watchlist;
+pub mod webhook;
+
+/// Bars fetched per K-line request unless configured otherwise.
+pub const DEFAULT_KLINE_BARS: u32 = 100;
 
 #[derive(Debug, Clone)]
 pub struct Back {
-    stock_codes: Vec<String>,
+    provider: Arc<dyn QuoteProvider>,
+    stock_codes: Vec<SecurityId>,
+    /// The last quotes polled, so exports do not move a replay along.
+    quotes: Vec<Quote>,
     kline_scale_map: HashMap<String, KLineScale>,
+    kline_bars: u32,
+    store: Option<KlineStore>,
+    recorder: Option<Recorder>,
+    /// While replaying, the live provider waits here.
+    live: Option<Arc<dyn QuoteProvider>>,
+    replay: Option<Arc<ReplayProvider>>,
+    alerts: AlertEngine,
+    candles: CandleBuilder,
+    notifier: Notifier,
+    webhook: Webhook,
     back_tx: Sender<ToFrontend>,
     front_rx: Receiver<ToBackend>,
 }
 
 impl Back {
-    pub fn new(back_tx: Sender<ToFrontend>, front_rx: Receiver<ToBackend>, codes: String) -> Self {
-        let stock_codes = codes
-            .split(",")
-            .filter(|x| check_stock_code(x))
-            .map(|x| x.to_string())
-            .collect::<Vec<String>>();
+    pub fn new(
+        back_tx: Sender<ToFrontend>,
+        front_rx: Receiver<ToBackend>,
+        stock_codes: Vec<SecurityId>,
+        provider: Arc<dyn QuoteProvider>,
+    ) -> Self {
         Self {
+            provider,
             back_tx,
             front_rx,
             stock_codes,
+            quotes: vec![],
             kline_scale_map: HashMap::default(),
+            kline_bars: DEFAULT_KLINE_BARS,
+            store: None,
+            recorder: None,
+            live: None,
+            replay: None,
+            alerts: AlertEngine::default(),
+            candles: CandleBuilder::default(),
+            notifier: Notifier::default(),
+            webhook: Webhook::default(),
         }
     }
 
+    /// Scales chosen in an earlier session, keyed by symbol.
+    pub fn with_kline_scales(
+        mut self,
+        scales: impl IntoIterator<Item = (String, KLineScale)>,
+    ) -> Self {
+        self.kline_scale_map.extend(scales);
+        self
+    }
+
+    pub fn with_kline_bars(mut self, bars: u32) -> Self {
+        self.kline_bars = bars;
+        self
+    }
+
+    /// Keep fetched bars on disk and only fetch what is newer.
+    pub fn with_store(mut self, store: KlineStore) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// The recorder the provider writes to, switched by `SetRecording`.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+
     pub fn run(&mut self) {
         self.refetch_data();
+        self.stock_codes
+            .iter()
+            .for_each(|id| self.show_stored(id, self.scale_of(&id.symbol())));
         self.refresh_kline();
         let mut ticker = tick(Duration::from_millis(200));
         let kline_ticker = tick(Duration::from_secs(60));
@@ -60,25 +138,60 @@ impl Back {
                                                 self.add_stock(code);
                                     },
                                   ToBackend::StockDel(code) => {
-                                        self.stock_codes.retain(|x| x != &code);
+                                        self.stock_codes.retain(|x| x.symbol() != code);
+                                        self.candles.remove(&code);
                                         self.refetch_data();
 
                                     },
                                     ToBackend::StockKLine(code, scale) => {
-                                        let scale_int = scale.to_usize();
-                                        self.kline_scale_map.insert(code.clone(), scale);
-                                       match  Stock::get_kelines(&code, &scale_int, 100) {
-                                           Ok(l) => {
-
-                                               let dl = ToFrontend::Kline(code.clone(), l);
-                                               self.back_tx.send(dl).ok();
-                                           },
-                                           Err(err) =>{
-                                                println!("kline error {}",err);
-                                           },
-                                       }
+                                        if let Some(id) = self.find(&code) {
+                                            self.show_stored(id, &scale);
+                                            self.fetch_kline(id, &scale);
+                                        }
+                                        self.kline_scale_map.insert(code, scale);
 
                                         }
+                                    ToBackend::StockKLineHistory(code, scale, before, loaded) => {
+                                        if let Some(id) = self.find(&code).cloned() {
+                                            let this = self.clone();
+                                            std::thread::spawn(move || {
+                                                this.fetch_kline_history(&id, &scale, before, loaded);
+                                            });
+                                        }
+                                    }
+                                    ToBackend::SetKlineBars(bars) => {
+                                        self.kline_bars = bars;
+                                        let this = self.clone();
+                                        std::thread::spawn(move || {
+                                            this.refresh_kline();
+                                        });
+                                    }
+                                    ToBackend::SetAlerts(rules) => {
+                                        self.alerts.set_rules(rules);
+                                    }
+                                    ToBackend::SetAlertSound(path) => {
+                                        self.notifier.set_sound_file(&path);
+                                    }
+                                    ToBackend::SetWebhook(config) => {
+                                        self.webhook = Webhook::new(config);
+                                    }
+                                    ToBackend::SetRecording(enabled) => {
+                                        if let Some(recorder) = &self.recorder {
+                                            recorder.set_enabled(enabled);
+                                        }
+                                    }
+                                    ToBackend::StartReplay(path) => self.start_replay(&path),
+                                    ToBackend::StopReplay => self.stop_replay(),
+                                    ToBackend::Export(request) => {
+                                        let this = self.clone();
+                                        std::thread::spawn(move || this.export(request));
+                                    }
+                                    ToBackend::Replay(command) => {
+                                        if let Some(replay) = &self.replay {
+                                            replay.apply(command);
+                                            self.refetch_data();
+                                        }
+                                    }
                                     }}
                          Err(e) => {
                                 error!("receive ToBackend msg faild : {}",e)
@@ -100,43 +213,92 @@ impl Back {
         }
     }
 
-    fn add_stock(&mut self, code: String) {
-        if !self.stock_codes.contains(&code) {
-            match stock::fetch_blocking(vec![code.to_string()]) {
+    /// Serve quotes from a recording until `stop_replay`.
+    fn start_replay(&mut self, path: &std::path::Path) {
+        match ReplayProvider::open(path) {
+            Ok(replay) => {
+                let replay = Arc::new(replay);
+                let live = std::mem::replace(&mut self.provider, replay.clone());
+                // replaying another file keeps the original live provider
+                self.live.get_or_insert(live);
+                self.replay = Some(replay);
+                self.refetch_data();
+            }
+            Err(e) => {
+                error!("open recording {} failed: {}", path.display(), e);
+                self.back_tx.send(ToFrontend::Replay(None)).ok();
+            }
+        }
+    }
+
+    fn stop_replay(&mut self) {
+        if let Some(live) = self.live.take() {
+            self.provider = live;
+        }
+        self.replay = None;
+        self.back_tx.send(ToFrontend::Replay(None)).ok();
+        self.refetch_data();
+    }
+
+    fn find(&self, code: &str) -> Option<&SecurityId> {
+        self.stock_codes.iter().find(|x| x.symbol() == code)
+    }
+
+    fn add_stock(&mut self, id: SecurityId) {
+        if !self.stock_codes.contains(&id) {
+            match self.provider.fetch(std::slice::from_ref(&id)) {
                 Ok(datas) => {
-                    datas.iter().for_each(|(code, name, data)| {
+                    self.report_errors(datas.errors);
+                    datas.items.iter().for_each(|(code, name, data)| {
                         let dl = ToFrontend::Data(code.clone(), name.clone(), data.clone());
                         self.back_tx.send(dl).ok();
-                        self.stock_codes.push(code.clone());
-
-                        let scale = self
-                            .kline_scale_map
-                            .get(code)
-                            .unwrap_or(&KLineScale::Munute15);
-                        match Stock::get_kelines(code, &scale.to_usize(), 100) {
-                            Ok(kl) => {
-                                self.back_tx.send(ToFrontend::Kline(code.clone(), kl)).ok();
-                            }
-                            Err(err) => {
-                                println!("get kline error {}", err);
-                            }
-                        }
+
+                        let scale = self.scale_of(code);
+                        self.show_stored(&id, scale);
+                        self.fetch_kline(&id, scale);
                     });
+                    if !datas.items.is_empty() {
+                        self.stock_codes.push(id);
+                    }
                 }
                 Err(e) => {
                     error!("add stock  error {}", e)
                 }
             }
         } else {
-            println!("stock {} already exists", code);
+            debug!("stock {} already exists", id);
         }
     }
 
-    fn refetch_data(&self) {
-        if !self.stock_codes.is_empty() {
-            match stock::fetch_blocking(self.stock_codes.clone()) {
+    fn refetch_data(&mut self) {
+        // a replay plays whatever was recorded
+        if !self.stock_codes.is_empty() || self.replay.is_some() {
+            match self.provider.fetch(&self.stock_codes) {
                 Ok(datas) => {
-                    let dl = ToFrontend::DataList(datas);
+                    self.report_errors(datas.errors);
+                    if let Some(replay) = &self.replay {
+                        self.back_tx
+                            .send(ToFrontend::Replay(Some(replay.status())))
+                            .ok();
+                    }
+                    // replayed quotes neither notify anyone nor make bars
+                    if self.replay.is_none() {
+                        let alerts = self.alerts.evaluate(
+                            datas
+                                .items
+                                .iter()
+                                .map(|(code, name, data)| (code.as_str(), name.as_str(), data)),
+                        );
+                        alerts.into_iter().for_each(|a| {
+                            debug!("alert: {}", a);
+                            self.notifier.deliver(&a);
+                            self.webhook.deliver(&a);
+                            self.back_tx.send(ToFrontend::Alert(a)).ok();
+                        });
+                        self.build_candles(&datas.items);
+                    }
+                    self.quotes = datas.items.clone();
+                    let dl = ToFrontend::DataList(datas.items);
                     self.back_tx.send(dl).ok();
                 }
                 Err(e) => {
@@ -146,22 +308,285 @@ impl Back {
         }
     }
 
+    /// Keep the forming bar of each A-share current between kline fetches,
+    /// and push it for the scale on screen.
+    fn build_candles(&mut self, items: &[(String, String, BaseData)]) {
+        for (code, _, data) in items {
+            if !self.find(code).is_some_and(|id| id.is_a_share()) {
+                continue;
+            }
+            if !self.candles.update(code, data) {
+                continue;
+            }
+            let scale = self
+                .kline_scale_map
+                .get(code)
+                .cloned()
+                .unwrap_or_default();
+            if !LOCAL_SCALES.contains(&scale) {
+                continue;
+            }
+            if let Some(bar) = self.candles.bar(code, &scale) {
+                let msg = ToFrontend::KlineBar(code.clone(), scale, bar.clone());
+                self.back_tx.send(msg).ok();
+            }
+        }
+    }
+
     fn refresh_kline(&self) {
         if !self.stock_codes.is_empty() {
-            self.stock_codes.iter().for_each(|code| {
-                let scale = self
-                    .kline_scale_map
-                    .get(code)
-                    .unwrap_or(&KLineScale::Munute15);
-                match Stock::get_kelines(code, &scale.to_usize(), 100) {
-                    Ok(kl) => {
-                        self.back_tx.send(ToFrontend::Kline(code.clone(), kl)).ok();
-                    }
-                    Err(err) => {
-                        println!("get kline error {}", err);
-                    }
-                }
+            self.stock_codes.iter().for_each(|id| {
+                self.fetch_kline(id, self.scale_of(&id.symbol()));
             });
         }
     }
+
+    fn scale_of(&self, code: &str) -> &KLineScale {
+        self.kline_scale_map
+            .get(code)
+            .unwrap_or(&KLineScale::Munute15)
+    }
+
+    /// The store of `scale`, if it keeps that scale.
+    fn store_for(&self, scale: &KLineScale) -> Option<&KlineStore> {
+        self.store.as_ref().filter(|_| KlineStore::keeps(scale))
+    }
+
+    /// Put stored bars on screen while the fetch is under way.
+    fn show_stored(&self, id: &SecurityId, scale: &KLineScale) {
+        let Some(store) = self.store_for(scale) else {
+            return;
+        };
+        let stored = store.load(&id.symbol(), scale);
+        if !stored.is_empty() {
+            let items = newest(stored, self.kline_bars);
+            self.back_tx
+                .send(ToFrontend::Kline(id.symbol(), scale.clone(), items))
+                .ok();
+        }
+    }
+
+    fn fetch_kline(&self, id: &SecurityId, scale: &KLineScale) {
+        let Some(store) = self.store_for(scale) else {
+            if let Some(items) = self.fetch_bars(id, scale, self.kline_bars) {
+                self.back_tx
+                    .send(ToFrontend::Kline(id.symbol(), scale.clone(), items))
+                    .ok();
+            }
+            return;
+        };
+        let symbol = id.symbol();
+        let last = store.load(&symbol, scale).last().map(|x| x.day);
+        let bars = last.map_or(self.kline_bars, |day| {
+            bars_since(day, scale).min(self.kline_bars)
+        });
+        let Some(mut items) = self.fetch_bars(id, scale, bars) else {
+            return;
+        };
+        // too few to reach the stored ones, e.g. after a long weekend
+        let gap = |items: &[KlineItem]| match (last, items.first()) {
+            (Some(last), Some(first)) => first.day > last,
+            _ => false,
+        };
+        if gap(&items) && bars < self.kline_bars {
+            match self.fetch_bars(id, scale, self.kline_bars) {
+                Some(more) => items = more,
+                None => return,
+            }
+        }
+        if gap(&items) {
+            debug!("{} {} history has a gap before {}", symbol, scale.label(), items[0].day);
+        }
+        match store.merge(&symbol, scale, &items) {
+            Ok(all) => items = newest(all, self.kline_bars),
+            Err(e) => error!("store {} klines failed: {}", symbol, e),
+        }
+        self.back_tx
+            .send(ToFrontend::Kline(symbol, scale.clone(), items))
+            .ok();
+    }
+
+    fn fetch_bars(&self, id: &SecurityId, scale: &KLineScale, bars: u32) -> Option<Vec<KlineItem>> {
+        match self.provider.klines(id, scale, bars) {
+            Ok(kl) => {
+                self.report_errors(kl.errors);
+                Some(kl.items)
+            }
+            Err(err) => {
+                error!("get kline error {}", err);
+                None
+            }
+        }
+    }
+
+    /// A page of `kline_bars` bars before `before`, `loaded` bars being on
+    /// screen already. Stored bars are used first, then the provider.
+    fn fetch_kline_history(
+        &self,
+        id: &SecurityId,
+        scale: &KLineScale,
+        before: NaiveDateTime,
+        loaded: u32,
+    ) {
+        let symbol = id.symbol();
+        let page = self.kline_bars;
+        let older = |items: Vec<KlineItem>| {
+            items
+                .into_iter()
+                .filter(|x| x.day < before)
+                .collect::<Vec<KlineItem>>()
+        };
+        let stored = self
+            .store_for(scale)
+            .map(|store| older(store.load(&symbol, scale)))
+            .unwrap_or_default();
+        let (items, exhausted) = if stored.len() >= page as usize {
+            (newest(stored, page), false)
+        } else {
+            let fetched = self.fetch_page(id, scale, before, loaded);
+            match (fetched, self.store_for(scale)) {
+                (Some(fetched), Some(store)) => {
+                    // a page ending before the present must not cut off the
+                    // newer stored bars
+                    let stored = if fetched.current {
+                        store.merge(&symbol, scale, &fetched.items)
+                    } else {
+                        store.prepend(&symbol, scale, &fetched.items)
+                    };
+                    let all = match stored {
+                        Ok(all) => older(all),
+                        Err(e) => {
+                            error!("store {} klines failed: {}", symbol, e);
+                            older(fetched.items)
+                        }
+                    };
+                    // the store may reach back further than the provider
+                    let exhausted = fetched.exhausted && all.len() <= page as usize;
+                    (newest(all, page), exhausted)
+                }
+                (Some(fetched), None) => (newest(older(fetched.items), page), fetched.exhausted),
+                (None, _) => (stored, false),
+            }
+        };
+        self.back_tx
+            .send(ToFrontend::KlineHistory(
+                symbol,
+                scale.clone(),
+                items,
+                exhausted,
+            ))
+            .ok();
+    }
+
+    /// Bars older than `before` from the provider. Providers without an end
+    /// date only serve the newest bars, so enough are asked for to reach past
+    /// the `loaded` ones; an answer shorter than asked means the provider's
+    /// cap was hit.
+    fn fetch_page(
+        &self,
+        id: &SecurityId,
+        scale: &KLineScale,
+        before: NaiveDateTime,
+        loaded: u32,
+    ) -> Option<HistoryPage> {
+        let page = self.kline_bars;
+        match self.provider.klines_before(id, scale, before, page) {
+            Ok(kl) => {
+                self.report_errors(kl.errors);
+                return Some(HistoryPage {
+                    exhausted: kl.items.len() < page as usize,
+                    items: kl.items,
+                    current: false,
+                });
+            }
+            Err(ProviderError::Unsupported(_)) => {}
+            Err(err) => {
+                error!("get kline history error {}", err);
+                return None;
+            }
+        }
+        let want = loaded.saturating_add(page);
+        let items = self.fetch_bars(id, scale, want)?;
+        Some(HistoryPage {
+            exhausted: items.len() < want as usize,
+            items,
+            current: true,
+        })
+    }
+
+    /// Everything known of `id` at `scale`: stored bars topped up with a
+    /// fresh fetch, or whichever of the two is there.
+    fn all_klines(&self, id: &SecurityId, scale: &KLineScale) -> Vec<KlineItem> {
+        let fetched = self.fetch_bars(id, scale, self.kline_bars);
+        match (self.store_for(scale), fetched) {
+            (Some(store), Some(items)) => {
+                store.merge(&id.symbol(), scale, &items).unwrap_or_else(|e| {
+                    error!("store {} klines failed: {}", id, e);
+                    items
+                })
+            }
+            (Some(store), None) => store.load(&id.symbol(), scale),
+            (None, items) => items.unwrap_or_default(),
+        }
+    }
+
+    fn export(&self, request: ExportRequest) {
+        let path = &request.path;
+        let written = match &request.what {
+            ExportWhat::Quotes if self.quotes.is_empty() => {
+                Err(ExportError::NoData("no quotes yet".to_string()))
+            }
+            ExportWhat::Quotes => export::export_quotes(path, request.format, &self.quotes),
+            ExportWhat::Klines {
+                code,
+                scale,
+                from,
+                to,
+            } => match self.find(code) {
+                Some(id) => {
+                    let klines = self.all_klines(id, scale);
+                    export::export_klines(path, request.format, code, scale, &klines, *from, *to)
+                }
+                None => Err(ExportError::NoData(format!("{} is not watched", code))),
+            },
+        };
+        if let Err(e) = &written {
+            error!("export to {} failed: {}", path.display(), e);
+        }
+        let result = written
+            .map(|rows| (request.path.clone(), rows))
+            .map_err(|e| e.to_string());
+        self.back_tx.send(ToFrontend::Exported(result)).ok();
+    }
+
+    /// Rows that failed to decode are skipped; let the UI know about them.
+    fn report_errors(&self, errors: Vec<QuoteParseError>) {
+        if !errors.is_empty() {
+            errors.iter().for_each(|e| debug!("skipped row: {}", e));
+            self.back_tx.send(ToFrontend::ParseErrors(errors)).ok();
+        }
+    }
+}
+
+/// Older bars from the provider.
+struct HistoryPage {
+    items: Vec<KlineItem>,
+    /// The provider has nothing before these.
+    exhausted: bool,
+    /// The bars run up to now rather than stopping at the requested end.
+    current: bool,
+}
+
+/// The newest `n` of `bars`.
+fn newest(mut bars: Vec<KlineItem>, n: u32) -> Vec<KlineItem> {
+    bars.drain(..bars.len().saturating_sub(n as usize));
+    bars
+}
+
+/// Enough bars to get from `last` to now, generously counted as if the
+/// market never closed. Bar times are Beijing time.
+fn bars_since(last: chrono::NaiveDateTime, scale: &KLineScale) -> u32 {
+    let now = Utc::now().naive_utc() + TimeDelta::hours(8);
+    let minutes = (now - last).num_minutes().max(0) as u64;
+    (minutes / scale.to_usize() as u64 + 2).min(u32::MAX as u64) as u32
 }
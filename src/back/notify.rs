@@ -0,0 +1,77 @@
+use std::path::PathBuf;
+
+use tracing::{debug, error};
+
+use super::alert::Alert;
+
+/// Delivers alerts outside the egui window, so they reach us while it is
+/// minimised or behind other apps.
+#[derive(Clone, Debug, Default)]
+pub struct Notifier {
+    sound_file: Option<PathBuf>,
+}
+
+impl Notifier {
+    pub fn set_sound_file(&mut self, path: &str) {
+        let path = path.trim();
+        self.sound_file = (!path.is_empty()).then(|| PathBuf::from(path));
+    }
+
+    /// Fire the channels the rule asked for, each on its own thread; a
+    /// missing notification daemon or audio device only gets logged.
+    pub fn deliver(&self, alert: &Alert) {
+        if alert.channels.desktop {
+            let summary = format!("{} {}", alert.name, alert.code);
+            let body = format!(
+                "{} at {} ({})",
+                alert.condition,
+                alert.value,
+                alert.at.format("%H:%M:%S")
+            );
+            std::thread::spawn(move || {
+                let shown = notify_rust::Notification::new()
+                    .appname("stock-tracker")
+                    .summary(&summary)
+                    .body(&body)
+                    .show();
+                if let Err(e) = shown {
+                    error!("desktop notification failed: {}", e);
+                }
+            });
+        }
+        if alert.channels.sound {
+            match &self.sound_file {
+                Some(path) => {
+                    let path = path.clone();
+                    std::thread::spawn(move || play(path));
+                }
+                None => debug!("no alert sound file set"),
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sound")]
+fn play(path: PathBuf) {
+    if let Err(e) = try_play(&path) {
+        error!("play {} failed: {}", path.display(), e);
+    }
+}
+
+#[cfg(feature = "sound")]
+fn try_play(path: &std::path::Path) -> Result<(), Box<dyn std::error::Error>> {
+    let (_stream, handle) = rodio::OutputStream::try_default()?;
+    let sink = rodio::Sink::try_new(&handle)?;
+    let file = std::io::BufReader::new(std::fs::File::open(path)?);
+    sink.append(rodio::Decoder::new(file)?);
+    sink.sleep_until_end();
+    Ok(())
+}
+
+#[cfg(not(feature = "sound"))]
+fn play(path: PathBuf) {
+    debug!(
+        "built without the `sound` feature, not playing {}",
+        path.display()
+    );
+}
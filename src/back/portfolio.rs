@@ -0,0 +1,94 @@
+use std::collections::{BTreeMap, HashMap};
+
+use serde::{Deserialize, Serialize};
+
+use super::stock::{BaseData, Currency};
+
+/// A position in one code.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Holding {
+    pub quantity: f64,
+    pub avg_cost: f64,
+    pub account: String,
+}
+
+/// Holdings keyed by stock code (`sh600519`).
+#[derive(Clone, Debug, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Portfolio {
+    pub holdings: HashMap<String, Holding>,
+}
+
+/// Valuation of a holding, or a sum of them, at the latest quote.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Pnl {
+    pub market_value: f64,
+    /// Against the previous close.
+    pub day: f64,
+    /// Against the average cost.
+    pub total: f64,
+    pub cost: f64,
+}
+
+impl Pnl {
+    pub fn total_per(&self) -> f64 {
+        if self.cost.abs() > f64::EPSILON {
+            self.total / self.cost * 100.0
+        } else {
+            0.0
+        }
+    }
+}
+
+impl std::ops::AddAssign for Pnl {
+    fn add_assign(&mut self, rhs: Self) {
+        self.market_value += rhs.market_value;
+        self.day += rhs.day;
+        self.total += rhs.total;
+        self.cost += rhs.cost;
+    }
+}
+
+impl Holding {
+    pub fn is_empty(&self) -> bool {
+        self.quantity.abs() < f64::EPSILON
+    }
+
+    pub fn pnl(&self, data: &BaseData) -> Pnl {
+        let new = data.new as f64;
+        let closing = data.closing as f64;
+        let cost = self.quantity * self.avg_cost;
+        let market_value = self.quantity * new;
+        Pnl {
+            market_value,
+            day: self.quantity * (new - closing),
+            total: market_value - cost,
+            cost,
+        }
+    }
+}
+
+impl Portfolio {
+    pub fn get(&self, code: &str) -> Option<&Holding> {
+        self.holdings.get(code).filter(|h| !h.is_empty())
+    }
+
+    pub fn pnl(&self, code: &str, data: &BaseData) -> Option<Pnl> {
+        self.get(code).map(|h| h.pnl(data))
+    }
+
+    /// Sum positions per currency; HKD and USD holdings are not converted.
+    pub fn totals<'a>(
+        &self,
+        quotes: impl IntoIterator<Item = (&'a str, &'a BaseData)>,
+    ) -> BTreeMap<Currency, Pnl> {
+        let mut totals = BTreeMap::new();
+        for (code, data) in quotes {
+            if let Some(pnl) = self.pnl(code, data) {
+                *totals.entry(data.currency).or_insert_with(Pnl::default) += pnl;
+            }
+        }
+        totals
+    }
+}
@@ -0,0 +1,259 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use chrono::NaiveDateTime;
+use tracing::{info, warn};
+
+use super::{Parsed, ProviderError, Quote, QuoteProvider};
+use crate::back::security::SecurityId;
+use crate::back::stock::{KLineScale, KlineItem};
+
+/// Serves quotes from `primary`, switching to `secondary` after `threshold`
+/// consecutive failures. While failed over, `primary` is probed every
+/// `probe_every` polls and takes over again as soon as it answers.
+#[derive(Debug)]
+pub struct FailoverProvider {
+    primary: Arc<dyn QuoteProvider>,
+    secondary: Arc<dyn QuoteProvider>,
+    threshold: u32,
+    probe_every: u32,
+    state: Mutex<FailoverState>,
+}
+
+#[derive(Debug, Default)]
+struct FailoverState {
+    failures: u32,
+    on_secondary: bool,
+    polls_since_probe: u32,
+}
+
+impl FailoverProvider {
+    pub fn new(
+        primary: Arc<dyn QuoteProvider>,
+        secondary: Arc<dyn QuoteProvider>,
+        threshold: u32,
+    ) -> Self {
+        Self {
+            primary,
+            secondary,
+            threshold: threshold.max(1),
+            probe_every: 25,
+            state: Mutex::new(FailoverState::default()),
+        }
+    }
+
+    pub fn probe_every(mut self, polls: u32) -> Self {
+        self.probe_every = polls.max(1);
+        self
+    }
+
+    fn on_secondary(&self) -> bool {
+        self.state().on_secondary
+    }
+
+    fn state(&self) -> MutexGuard<'_, FailoverState> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Decide who answers this poll. The lock is only held for the
+    /// bookkeeping, so `klines` is never stuck behind a slow quote request.
+    fn route(&self) -> Route {
+        let mut state = self.state();
+        if !state.on_secondary {
+            return Route::Primary;
+        }
+        state.polls_since_probe += 1;
+        if state.polls_since_probe < self.probe_every {
+            return Route::Secondary;
+        }
+        state.polls_since_probe = 0;
+        Route::Probe
+    }
+}
+
+enum Route {
+    Primary,
+    Secondary,
+    /// Failed over, but trying `primary` again.
+    Probe,
+}
+
+impl QuoteProvider for FailoverProvider {
+    fn name(&self) -> &str {
+        if self.on_secondary() {
+            self.secondary.name()
+        } else {
+            self.primary.name()
+        }
+    }
+
+    fn fetch(&self, codes: &[SecurityId]) -> Result<Parsed<Quote>, ProviderError> {
+        match self.route() {
+            Route::Secondary => self.secondary.fetch(codes),
+            Route::Probe => match self.primary.fetch(codes) {
+                Ok(datas) => {
+                    info!("{} recovered, switching back", self.primary.name());
+                    *self.state() = FailoverState::default();
+                    Ok(datas)
+                }
+                Err(_) => self.secondary.fetch(codes),
+            },
+            Route::Primary => match self.primary.fetch(codes) {
+                Ok(datas) => {
+                    self.state().failures = 0;
+                    Ok(datas)
+                }
+                Err(e) => {
+                    {
+                        let mut state = self.state();
+                        state.failures += 1;
+                        if state.failures < self.threshold {
+                            return Err(e);
+                        }
+                        warn!(
+                            "{} failed {} times in a row ({}), failing over to {}",
+                            self.primary.name(),
+                            state.failures,
+                            e,
+                            self.secondary.name()
+                        );
+                        state.on_secondary = true;
+                        state.polls_since_probe = 0;
+                    }
+                    self.secondary.fetch(codes)
+                }
+            },
+        }
+    }
+
+    fn klines(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        let (first, second) = if self.on_secondary() {
+            (&self.secondary, &self.primary)
+        } else {
+            (&self.primary, &self.secondary)
+        };
+        first
+            .klines(code, scale, datalen)
+            .or_else(|_| second.klines(code, scale, datalen))
+    }
+
+    /// Pages come from the provider serving `klines`, so older bars match
+    /// the newer ones; it not paging by date is no reason to ask the other.
+    fn klines_before(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        before: NaiveDateTime,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        let (first, second) = if self.on_secondary() {
+            (&self.secondary, &self.primary)
+        } else {
+            (&self.primary, &self.secondary)
+        };
+        match first.klines_before(code, scale, before, datalen) {
+            Err(ProviderError::Unsupported(what)) => Err(ProviderError::Unsupported(what)),
+            Err(_) => second.klines_before(code, scale, before, datalen),
+            ok => ok,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::back::stock::BaseData;
+
+    /// Answers with one quote named after itself, or fails when told to.
+    #[derive(Debug)]
+    struct Mock {
+        name: &'static str,
+        failing: AtomicBool,
+        calls: AtomicUsize,
+    }
+
+    impl Mock {
+        fn new(name: &'static str, failing: bool) -> Arc<Self> {
+            Arc::new(Self {
+                name,
+                failing: AtomicBool::new(failing),
+                calls: AtomicUsize::new(0),
+            })
+        }
+
+        fn calls(&self) -> usize {
+            self.calls.load(Ordering::SeqCst)
+        }
+    }
+
+    impl QuoteProvider for Mock {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        fn fetch(&self, codes: &[SecurityId]) -> Result<Parsed<Quote>, ProviderError> {
+            self.calls.fetch_add(1, Ordering::SeqCst);
+            if self.failing.load(Ordering::SeqCst) {
+                return Err(ProviderError::NoQuotes);
+            }
+            Ok(Parsed {
+                items: codes
+                    .iter()
+                    .map(|id| (id.symbol(), self.name.to_string(), BaseData::default()))
+                    .collect(),
+                errors: vec![],
+            })
+        }
+
+        fn klines(
+            &self,
+            _: &SecurityId,
+            _: &KLineScale,
+            _: u32,
+        ) -> Result<Parsed<KlineItem>, ProviderError> {
+            Ok(Parsed::default())
+        }
+    }
+
+    fn answered_by(result: Result<Parsed<Quote>, ProviderError>) -> String {
+        result.expect("a quote").items[0].1.clone()
+    }
+
+    #[test]
+    fn fails_over_after_threshold_and_recovers_on_probe() {
+        let primary = Mock::new("primary", true);
+        let secondary = Mock::new("secondary", false);
+        let provider = FailoverProvider::new(primary.clone(), secondary.clone(), 2).probe_every(3);
+        let codes = [SecurityId::parse("sh600000").unwrap()];
+
+        // below the threshold the failure is passed on
+        assert!(provider.fetch(&codes).is_err());
+        assert_eq!(provider.name(), "primary");
+        assert_eq!(answered_by(provider.fetch(&codes)), "secondary");
+        assert_eq!(provider.name(), "secondary");
+        assert_eq!(primary.calls(), 2);
+
+        // two polls on the secondary, then a probe that still fails
+        for _ in 0..3 {
+            assert_eq!(answered_by(provider.fetch(&codes)), "secondary");
+        }
+        assert_eq!(primary.calls(), 3);
+        assert_eq!(secondary.calls(), 4);
+
+        primary.failing.store(false, Ordering::SeqCst);
+        for _ in 0..2 {
+            assert_eq!(answered_by(provider.fetch(&codes)), "secondary");
+        }
+        assert_eq!(primary.calls(), 3);
+        assert_eq!(answered_by(provider.fetch(&codes)), "primary");
+        assert_eq!(provider.name(), "primary");
+        assert_eq!(answered_by(provider.fetch(&codes)), "primary");
+        assert_eq!(secondary.calls(), 6);
+    }
+}
@@ -0,0 +1,157 @@
+use std::fmt::{self, Debug, Display};
+
+use chrono::NaiveDateTime;
+use encoding_rs::{Encoding, GBK, WINDOWS_1252};
+use reqwest::{blocking::Response, header::CONTENT_TYPE};
+
+use super::{
+    security::SecurityId,
+    stock::{BaseData, KLineScale, KlineItem, QuoteParseError},
+};
+
+pub mod failover;
+pub mod record;
+pub mod replay;
+pub mod sina;
+pub mod tencent;
+
+pub use failover::FailoverProvider;
+pub use replay::ReplayProvider;
+pub use sina::SinaProvider;
+pub use tencent::TencentProvider;
+
+/// `(code, name, data)` as decoded from a realtime quote line.
+pub type Quote = (String, String, BaseData);
+
+/// Rows that decoded, plus the ones that were skipped and why.
+#[derive(Debug)]
+pub struct Parsed<T> {
+    pub items: Vec<T>,
+    pub errors: Vec<QuoteParseError>,
+}
+
+impl<T> Default for Parsed<T> {
+    fn default() -> Self {
+        Self {
+            items: vec![],
+            errors: vec![],
+        }
+    }
+}
+
+impl<T> FromIterator<Result<T, QuoteParseError>> for Parsed<T> {
+    fn from_iter<I: IntoIterator<Item = Result<T, QuoteParseError>>>(iter: I) -> Self {
+        let mut parsed = Self::default();
+        for r in iter {
+            match r {
+                Ok(item) => parsed.items.push(item),
+                Err(e) => parsed.errors.push(e),
+            }
+        }
+        parsed
+    }
+}
+
+/// A source of realtime snapshots and kline history.
+///
+/// `Back` only talks to quote vendors through this trait, so a different feed
+/// (or a local mock server) can be plugged in when the backend is created.
+pub trait QuoteProvider: Debug + Send + Sync {
+    /// Short name used in logs.
+    fn name(&self) -> &str;
+
+    /// Fetch the latest snapshot for every code in `codes`.
+    fn fetch(&self, codes: &[SecurityId]) -> Result<Parsed<Quote>, ProviderError>;
+
+    /// Fetch the last `datalen` bars of `code` at `scale`. Vendors cap
+    /// `datalen`, answering with fewer bars than asked past the cap.
+    fn klines(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError>;
+
+    /// Fetch the last `datalen` bars of `code` at `scale` before `before`,
+    /// for vendors that take an end date. The others answer `Unsupported`
+    /// and older history has to be cut from a longer `klines` request.
+    fn klines_before(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        _before: NaiveDateTime,
+        _datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        Err(ProviderError::Unsupported(format!(
+            "{} {:?} klines by end date for {}",
+            self.name(),
+            scale,
+            code
+        )))
+    }
+}
+
+#[derive(Debug)]
+pub enum ProviderError {
+    Http(reqwest::Error),
+    Unsupported(String),
+    /// The server answered, but not one of the requested quotes decoded.
+    NoQuotes,
+}
+
+impl Display for ProviderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProviderError::Http(e) => write!(f, "http error: {}", e),
+            ProviderError::Unsupported(what) => write!(f, "unsupported: {}", what),
+            ProviderError::NoQuotes => write!(f, "no quotes in response"),
+        }
+    }
+}
+
+impl std::error::Error for ProviderError {}
+
+impl From<reqwest::Error> for ProviderError {
+    fn from(e: reqwest::Error) -> Self {
+        ProviderError::Http(e)
+    }
+}
+
+/// Decode a quote response body, failing on a non-2xx status such as Sina's
+/// 403 for a missing Referer.
+///
+/// Chinese vendors answer in GBK and often omit the charset or send a generic
+/// one, so GBK is assumed unless the server names something more specific.
+pub(crate) fn decode_body(resp: Response) -> Result<String, ProviderError> {
+    let resp = resp.error_for_status()?;
+    let encoding = resp
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| {
+            v.split(';')
+                .filter_map(|p| p.trim().strip_prefix("charset="))
+                .next()
+                .and_then(|label| Encoding::for_label(label.trim_matches('"').as_bytes()))
+        })
+        .filter(|e| *e != WINDOWS_1252)
+        .unwrap_or(GBK);
+    let bytes = resp.bytes()?;
+    let (text, _, had_errors) = encoding.decode(&bytes);
+    if had_errors {
+        tracing::debug!("malformed {} sequences in response", encoding.name());
+    }
+    Ok(text.into_owned())
+}
+
+/// A throttled or blocked vendor may still answer 200 with nothing usable, so
+/// asking for quotes and getting none back counts as a failure.
+pub(crate) fn require_quotes(
+    codes: &[SecurityId],
+    parsed: Parsed<Quote>,
+) -> Result<Parsed<Quote>, ProviderError> {
+    if !codes.is_empty() && parsed.items.is_empty() {
+        return Err(ProviderError::NoQuotes);
+    }
+    Ok(parsed)
+}
@@ -0,0 +1,216 @@
+//! Raw quote responses on disk, for replaying a session later.
+//!
+//! A recording is a JSON-lines file of [`Frame`]s. The recorder starts a new
+//! file once the current one passes `max_bytes` and drops the oldest files
+//! beyond `keep`.
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use chrono::{DateTime, Local};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+const EXTENSION: &str = "jsonl";
+
+/// One response body as it came off the wire.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct Frame {
+    pub at: DateTime<Local>,
+    pub body: String,
+}
+
+#[derive(Debug, Default)]
+struct Current {
+    file: Option<io::BufWriter<fs::File>>,
+    written: u64,
+}
+
+/// Appends response bodies to rotating files in `dir` while enabled.
+#[derive(Clone, Debug)]
+pub struct Recorder {
+    dir: PathBuf,
+    max_bytes: u64,
+    keep: usize,
+    enabled: Arc<AtomicBool>,
+    current: Arc<Mutex<Current>>,
+}
+
+impl Recorder {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            max_bytes: 32 * 1024 * 1024,
+            keep: 20,
+            enabled: Arc::default(),
+            current: Arc::default(),
+        }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+        if !enabled {
+            // the next recording starts a fresh file
+            let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(mut file) = current.file.take() {
+                file.flush().ok();
+            }
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    /// Store `body`; failures are logged, never returned, so a full disk
+    /// does not stop quotes.
+    pub fn record(&self, body: &str) {
+        if !self.is_enabled() {
+            return;
+        }
+        if let Err(e) = self.append(body) {
+            error!("record into {} failed: {}", self.dir.display(), e);
+        }
+    }
+
+    fn append(&self, body: &str) -> io::Result<()> {
+        let frame = Frame {
+            at: Local::now(),
+            body: body.to_string(),
+        };
+        let line = serde_json::to_string(&frame)? + "\n";
+        let mut current = self.current.lock().unwrap_or_else(|e| e.into_inner());
+        if current.written >= self.max_bytes {
+            current.file = None;
+        }
+        if current.file.is_none() {
+            fs::create_dir_all(&self.dir)?;
+            let path = self.dir.join(format!(
+                "quotes-{}.{}",
+                frame.at.format("%Y%m%d-%H%M%S%.3f"),
+                EXTENSION
+            ));
+            debug!("recording into {}", path.display());
+            current.file = Some(io::BufWriter::new(fs::File::create(path)?));
+            current.written = 0;
+            self.prune();
+        }
+        if let Some(file) = current.file.as_mut() {
+            file.write_all(line.as_bytes())?;
+            file.flush()?;
+        }
+        current.written += line.len() as u64;
+        Ok(())
+    }
+
+    fn prune(&self) {
+        let files = recordings(&self.dir);
+        let excess = files.len().saturating_sub(self.keep);
+        for path in &files[..excess] {
+            if let Err(e) = fs::remove_file(path) {
+                error!("remove {} failed: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+/// Recordings in `dir`, oldest first.
+pub fn recordings(dir: &Path) -> Vec<PathBuf> {
+    let mut files = fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .filter_map(|e| e.ok().map(|e| e.path()))
+                .filter(|p| p.extension().is_some_and(|x| x == EXTENSION))
+                .collect::<Vec<PathBuf>>()
+        })
+        .unwrap_or_default();
+    // names carry the start time, to the millisecond
+    files.sort();
+    files
+}
+
+/// Read a recording, skipping lines that do not parse.
+pub fn load(path: &Path) -> io::Result<Vec<Frame>> {
+    let file = io::BufReader::new(fs::File::open(path)?);
+    let mut frames = vec![];
+    for line in file.lines() {
+        match serde_json::from_str(&line?) {
+            Ok(frame) => frames.push(frame),
+            Err(e) => debug!("skipped recorded frame: {}", e),
+        }
+    }
+    Ok(frames)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{process, thread, time::Duration};
+
+    use super::*;
+
+    fn dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("recorder-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        dir
+    }
+
+    #[test]
+    fn rotates_and_keeps_the_newest_files() {
+        let defaults = Recorder::new("unused");
+        assert_eq!((defaults.max_bytes, defaults.keep), (32 * 1024 * 1024, 20));
+
+        // every frame passes the limit, so each one starts a file
+        let recorder = Recorder {
+            max_bytes: 1,
+            ..Recorder::new(dir("rotate"))
+        };
+        recorder.record("off");
+        recorder.set_enabled(true);
+        for i in 0..25 {
+            recorder.record(&format!("frame {}", i));
+            thread::sleep(Duration::from_millis(2));
+        }
+
+        let files = recordings(recorder.dir());
+        assert_eq!(files.len(), 20);
+        let first = load(&files[0]).unwrap();
+        let last = load(&files[19]).unwrap();
+        assert_eq!(first.len(), 1);
+        assert_eq!(first[0].body, "frame 5");
+        assert_eq!(last[0].body, "frame 24");
+        let _ = fs::remove_dir_all(recorder.dir());
+    }
+
+    #[test]
+    fn appends_and_starts_a_file_per_session() {
+        let recorder = Recorder::new(dir("append"));
+        recorder.set_enabled(true);
+        recorder.record("a");
+        recorder.record("b");
+        // switching off closes the file, the next session gets a new one
+        recorder.set_enabled(false);
+        recorder.record("dropped");
+        thread::sleep(Duration::from_millis(2));
+        recorder.set_enabled(true);
+        recorder.record("c");
+
+        let files = recordings(recorder.dir());
+        let bodies = files
+            .iter()
+            .map(|f| load(f).unwrap().into_iter().map(|x| x.body).collect())
+            .collect::<Vec<Vec<String>>>();
+        assert_eq!(bodies, [vec!["a", "b"], vec!["c"]]);
+        let _ = fs::remove_dir_all(recorder.dir());
+    }
+}
@@ -0,0 +1,259 @@
+//! Play a recording back in place of a live feed.
+
+use std::{path::Path, sync::Mutex, time::Instant};
+
+use chrono::{DateTime, Local};
+
+use super::{
+    record::{self, Frame},
+    sina, Parsed, ProviderError, Quote, QuoteProvider,
+};
+use crate::back::security::SecurityId;
+use crate::back::stock::{KLineScale, KlineItem};
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Speed {
+    #[default]
+    X1,
+    X10,
+    /// One recorded frame per poll.
+    Max,
+}
+
+impl Speed {
+    pub const ALL: [Speed; 3] = [Speed::X1, Speed::X10, Speed::Max];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Speed::X1 => "1x",
+            Speed::X10 => "10x",
+            Speed::Max => "max",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+pub enum ReplayCommand {
+    Pause(bool),
+    Speed(Speed),
+    /// Jump to a frame index.
+    Seek(usize),
+}
+
+/// Where playback stands, for the UI.
+#[derive(Clone, Debug)]
+pub struct ReplayStatus {
+    pub file: String,
+    pub position: usize,
+    pub len: usize,
+    pub at: DateTime<Local>,
+    pub paused: bool,
+    pub speed: Speed,
+}
+
+#[derive(Debug)]
+struct Playback {
+    position: usize,
+    paused: bool,
+    speed: Speed,
+    /// Wall clock and frame that playback last (re)started from.
+    since: Instant,
+    from: usize,
+}
+
+impl Playback {
+    fn restart(&mut self) {
+        self.since = Instant::now();
+        self.from = self.position;
+    }
+}
+
+/// Serves the frames of a recording as if they were live quotes, pacing
+/// them by their recorded timestamps. Klines are not recorded.
+#[derive(Debug)]
+pub struct ReplayProvider {
+    file: String,
+    frames: Vec<Frame>,
+    playback: Mutex<Playback>,
+}
+
+impl ReplayProvider {
+    pub fn open(path: &Path) -> std::io::Result<Self> {
+        let frames = record::load(path)?;
+        if frames.is_empty() {
+            return Err(std::io::Error::new(
+                std::io::ErrorKind::InvalidData,
+                "no frames in recording",
+            ));
+        }
+        Ok(Self {
+            file: path
+                .file_name()
+                .map(|x| x.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            frames,
+            playback: Mutex::new(Playback {
+                position: 0,
+                paused: false,
+                speed: Speed::default(),
+                since: Instant::now(),
+                from: 0,
+            }),
+        })
+    }
+
+    pub fn apply(&self, command: ReplayCommand) {
+        let mut playback = self.playback.lock().unwrap_or_else(|e| e.into_inner());
+        match command {
+            ReplayCommand::Pause(paused) => playback.paused = paused,
+            ReplayCommand::Speed(speed) => playback.speed = speed,
+            ReplayCommand::Seek(position) => {
+                playback.position = position.min(self.frames.len() - 1)
+            }
+        }
+        playback.restart();
+    }
+
+    pub fn status(&self) -> ReplayStatus {
+        let playback = self.playback.lock().unwrap_or_else(|e| e.into_inner());
+        ReplayStatus {
+            file: self.file.clone(),
+            position: playback.position,
+            len: self.frames.len(),
+            at: self.frames[playback.position].at,
+            paused: playback.paused,
+            speed: playback.speed,
+        }
+    }
+
+    /// Move to the frame due now and return it.
+    fn advance(&self) -> &Frame {
+        let mut playback = self.playback.lock().unwrap_or_else(|e| e.into_inner());
+        let last = self.frames.len() - 1;
+        if !playback.paused {
+            playback.position = match playback.speed {
+                Speed::Max => (playback.position + 1).min(last),
+                Speed::X1 | Speed::X10 => {
+                    let factor = if playback.speed == Speed::X10 { 10 } else { 1 };
+                    let elapsed = playback.since.elapsed() * factor;
+                    let due = self.frames[playback.from].at
+                        + chrono::Duration::from_std(elapsed).unwrap_or_default();
+                    let after = self.frames[playback.from..].partition_point(|f| f.at <= due);
+                    playback.from + after.saturating_sub(1)
+                }
+            };
+            if playback.position == last {
+                playback.paused = true;
+            }
+        }
+        &self.frames[playback.position]
+    }
+}
+
+impl QuoteProvider for ReplayProvider {
+    fn name(&self) -> &str {
+        "replay"
+    }
+
+    /// Every quote of the frame, whatever `codes` asks for.
+    fn fetch(&self, _codes: &[SecurityId]) -> Result<Parsed<Quote>, ProviderError> {
+        Ok(sina::decode_response(&self.advance().body))
+    }
+
+    fn klines(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        _datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        Err(ProviderError::Unsupported(format!(
+            "replayed {:?} klines for {}",
+            scale, code
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{fs, process, thread, time::Duration};
+
+    use chrono::TimeDelta;
+
+    use super::*;
+
+    /// A recording whose frames are `offsets` seconds apart from the first.
+    fn replay(name: &str, offsets: &[i64]) -> ReplayProvider {
+        let start = Local::now();
+        let lines = offsets
+            .iter()
+            .map(|&s| {
+                let frame = Frame {
+                    at: start + TimeDelta::seconds(s),
+                    body: format!("var hq_str_sh600519=\"frame {}\";", s),
+                };
+                serde_json::to_string(&frame).unwrap() + "\n"
+            })
+            .collect::<String>();
+        let path = std::env::temp_dir().join(format!("replay-{}-{}.jsonl", name, process::id()));
+        fs::write(&path, lines).unwrap();
+        let replay = ReplayProvider::open(&path).unwrap();
+        let _ = fs::remove_file(&path);
+        replay
+    }
+
+    /// Fetch once and return where playback stands.
+    fn step(replay: &ReplayProvider) -> usize {
+        replay.fetch(&[]).unwrap();
+        replay.status().position
+    }
+
+    #[test]
+    fn max_speed_steps_a_frame_per_poll_and_stops_at_the_end() {
+        let replay = replay("max", &[0, 1, 2]);
+        replay.apply(ReplayCommand::Speed(Speed::Max));
+        assert_eq!([step(&replay), step(&replay), step(&replay)], [1, 2, 2]);
+        assert!(replay.status().paused);
+    }
+
+    #[test]
+    fn pause_holds_the_frame() {
+        let replay = replay("pause", &[0, 1, 2]);
+        replay.apply(ReplayCommand::Speed(Speed::Max));
+        replay.apply(ReplayCommand::Pause(true));
+        assert_eq!([step(&replay), step(&replay)], [0, 0]);
+        replay.apply(ReplayCommand::Pause(false));
+        assert_eq!(step(&replay), 1);
+    }
+
+    #[test]
+    fn seek_jumps_and_clamps() {
+        let replay = replay("seek", &[0, 1, 2, 3]);
+        replay.apply(ReplayCommand::Seek(2));
+        let status = replay.status();
+        assert_eq!((status.position, status.len), (2, 4));
+        replay.apply(ReplayCommand::Seek(99));
+        assert_eq!(replay.status().position, 3);
+    }
+
+    #[test]
+    fn speed_scales_the_recorded_pace() {
+        let slow = replay("x1", &[0, 1, 2, 100]);
+        let fast = replay("x10", &[0, 1, 2, 100]);
+        fast.apply(ReplayCommand::Speed(Speed::X10));
+        thread::sleep(Duration::from_millis(250));
+        // 0.25s of wall clock is 2.5s of recording at 10x
+        assert_eq!(step(&slow), 0);
+        assert_eq!(step(&fast), 2);
+        assert_eq!(fast.status().speed, Speed::X10);
+    }
+
+    #[test]
+    fn klines_are_not_replayed() {
+        let replay = replay("klines", &[0]);
+        let id = SecurityId::parse("sh600519").unwrap();
+        assert!(matches!(
+            replay.klines(&id, &KLineScale::Day, 10),
+            Err(ProviderError::Unsupported(_))
+        ));
+    }
+}
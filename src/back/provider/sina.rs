@@ -0,0 +1,390 @@
+use reqwest::blocking::Client;
+
+use super::{
+    decode_body, record::Recorder, require_quotes, Parsed, ProviderError, Quote, QuoteProvider,
+};
+use crate::back::security::{Exchange, SecurityId};
+use crate::back::stock::{
+    parse_field, BaseData, Currency, Instrument, KLineScale, KlineItem, KlineItemD, Price,
+    QuoteParseError, Vol,
+};
+
+const BASE_URL: &str = "http://hq.sinajs.cn";
+const KLINE_URL: &str =
+    "https://quotes.sina.cn/cn/api/json_v2.php/CN_MarketDataService.getKLineData";
+
+mod futures;
+
+/// Quotes from hq.sinajs.cn and klines from quotes.sina.cn.
+///
+/// The kline service has no end date and caps `datalen` (at 1023 bars when
+/// this was written), so history reaches back that far and no further.
+#[derive(Debug, Clone)]
+pub struct SinaProvider {
+    client: Client,
+    base_url: String,
+    kline_url: String,
+    futures_kline_url: String,
+    recorder: Option<Recorder>,
+}
+
+impl Default for SinaProvider {
+    fn default() -> Self {
+        Self::with_urls(BASE_URL, KLINE_URL)
+    }
+}
+
+impl SinaProvider {
+    /// Point the provider somewhere other than Sina, e.g. a local test server
+    /// replaying captured responses.
+    pub fn with_urls(base_url: &str, kline_url: &str) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            kline_url: kline_url.to_string(),
+            futures_kline_url: futures::KLINE_URL.to_string(),
+            recorder: None,
+        }
+    }
+
+    /// Hand every quote response to `recorder` before decoding it.
+    pub fn with_recorder(mut self, recorder: Recorder) -> Self {
+        self.recorder = Some(recorder);
+        self
+    }
+}
+
+impl QuoteProvider for SinaProvider {
+    fn name(&self) -> &str {
+        "sina"
+    }
+
+    fn fetch(&self, codes: &[SecurityId]) -> Result<Parsed<Quote>, ProviderError> {
+        let list = codes
+            .iter()
+            .map(sina_symbol)
+            .collect::<Vec<String>>()
+            .join(",");
+        let url = format!("{}/list={}", self.base_url, list);
+
+        let resp = self
+            .client
+            .get(&url)
+            .header("Referer", "https://www.sina.com.cn/")
+            .send()?;
+        let str = decode_body(resp)?;
+        if let Some(recorder) = &self.recorder {
+            recorder.record(&str);
+        }
+        require_quotes(codes, decode_response(&str))
+    }
+
+    fn klines(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        if code.is_futures() {
+            return futures::klines(&self.client, &self.futures_kline_url, code, scale, datalen);
+        }
+        // Sina has no one-minute bars; Tencent serves the intraday chart
+        if !code.is_a_share() || *scale == KLineScale::Intraday {
+            return Err(ProviderError::Unsupported(format!(
+                "sina {:?} klines for {}",
+                scale, code
+            )));
+        }
+        let scale = scale.to_usize();
+        let l = self
+            .client
+            .get(format!(
+                "{}?symbol={code}&scale={scale}&ma=no&datalen={datalen}",
+                self.kline_url
+            ))
+            .send()?
+            .error_for_status()?
+            .json::<Vec<KlineItemD>>()?;
+        let d = l.into_iter().map(KlineItem::try_from).collect();
+        Ok(d)
+    }
+}
+
+/// How Sina names `id` in quote requests: Hong Kong codes need the `rt_`
+/// prefix for realtime (rather than 15 minute delayed) quotes.
+fn sina_symbol(id: &SecurityId) -> String {
+    match id.exchange {
+        Exchange::Hk => format!("rt_{}", id.symbol()),
+        _ => id.symbol(),
+    }
+}
+
+/// Decode a whole quote response, one line per symbol.
+pub(crate) fn decode_response(body: &str) -> Parsed<Quote> {
+    body.trim()
+        .split('\n')
+        .filter(|x| !x.trim().is_empty() && !is_blank_quote(x))
+        .map(decode_from_string)
+        .collect()
+}
+
+/// Sina answers unknown, suspended or not yet trading symbols with an empty
+/// `var hq_str_rt_hk00700="";`, which means no data rather than a bad line.
+fn is_blank_quote(line: &str) -> bool {
+    line.split_once("=\"")
+        .is_some_and(|(_, body)| body.trim_end().trim_end_matches([';', '"']).is_empty())
+}
+
+/// Decode one `var hq_str_<symbol>="..."` line, picking the layout from the
+/// symbol's market.
+pub(crate) fn decode_from_string(stock_string: &str) -> Result<Quote, QuoteParseError> {
+    let (var, body) =
+        stock_string
+            .trim()
+            .split_once("=\"")
+            .ok_or_else(|| QuoteParseError::Layout {
+                raw: stock_string.to_string(),
+            })?;
+    let code = var
+        .trim()
+        .trim_start_matches("var hq_str_")
+        .trim_start_matches("rt_")
+        .to_string();
+    let fields: Vec<&str> = body.trim_end_matches([';', '"']).split(',').collect();
+
+    let (name, data) = if code.starts_with("hk") {
+        decode_hk(&fields)
+    } else if code.starts_with("gb_") {
+        decode_us(&fields)
+    } else if code.starts_with("nf_") {
+        futures::decode_futures(&fields)
+    } else {
+        decode_a_share(&fields)
+    }?;
+    Ok((code, name, data))
+}
+
+type Decoded = Result<(String, BaseData), QuoteParseError>;
+
+fn short_line(fields: &[&str]) -> QuoteParseError {
+    QuoteParseError::Layout {
+        raw: fields.join(","),
+    }
+}
+
+fn percent(new: Price, closing: Price) -> f32 {
+    if closing > 0.0 {
+        ((new - closing) / closing * 10000.0).round() / 100.0
+    } else {
+        0.0
+    }
+}
+
+/// `name,open,closing,new,high,low,bid,ask,vol,amount,<5 bids>,<5 asks>,date,time,...`
+fn decode_a_share(fields: &[&str]) -> Decoded {
+    let [name, opening, closing, new, high, low, bid, ask, vol, amount, ..] = fields else {
+        return Err(short_line(fields));
+    };
+    if fields.len() < 32 {
+        return Err(short_line(fields));
+    }
+    let closing = parse_field::<Price>("closing", closing)?;
+    let new = parse_field::<Price>("new", new)?;
+
+    let ladder = |side: &[&str]| -> Result<Vec<(Vol, Price)>, QuoteParseError> {
+        side.chunks(2)
+            .map(|x| match x {
+                [v, p] => Ok((
+                    parse_field::<Vol>("order volume", v)? / 100,
+                    parse_field::<Price>("order price", p)?,
+                )),
+                _ => Ok((0, 0.0)),
+            })
+            .collect()
+    };
+    let bids = ladder(&fields[10..20])?;
+    let asks = ladder(&fields[20..30])?;
+
+    let data = BaseData {
+        opening: parse_field("opening", opening)?,
+        closing,
+        new,
+        hight: parse_field("high", high)?,
+        low: parse_field("low", low)?,
+        bid: parse_field("bid", bid)?,
+        ask: parse_field("ask", ask)?,
+        vol: parse_field("vol", vol)?,
+        amount: parse_field("amount", amount)?,
+        date: fields[30].to_string(),
+        time: fields[31].to_string(),
+        rise_per: percent(new, closing),
+        bids,
+        asks,
+        currency: Currency::Cny,
+        instrument: Instrument::Equity,
+    };
+    Ok((name.to_string(), data))
+}
+
+/// `en_name,name,open,closing,high,low,new,change,percent,bid,ask,amount,vol,
+/// pe,yield,52w_high,52w_low,2024/09/25,16:08:00,...`
+///
+/// No order book is published, only the best bid and ask.
+fn decode_hk(fields: &[&str]) -> Decoded {
+    let [_, name, opening, closing, high, low, new, _, _, bid, ask, amount, vol, _, _, _, _, date, time, ..] =
+        fields
+    else {
+        return Err(short_line(fields));
+    };
+    let closing = parse_field::<Price>("closing", closing)?;
+    let new = parse_field::<Price>("new", new)?;
+    let data = BaseData {
+        opening: parse_field("opening", opening)?,
+        closing,
+        new,
+        hight: parse_field("high", high)?,
+        low: parse_field("low", low)?,
+        bid: parse_field("bid", bid)?,
+        ask: parse_field("ask", ask)?,
+        vol: parse_field::<f64>("vol", vol)? as Vol,
+        amount: parse_field("amount", amount)?,
+        date: date.replace('/', "-"),
+        time: time.to_string(),
+        rise_per: percent(new, closing),
+        bids: vec![],
+        asks: vec![],
+        currency: Currency::Hkd,
+        instrument: Instrument::Equity,
+    };
+    Ok((name.to_string(), data))
+}
+
+/// `name,new,percent,2024-09-26 04:05:52,change,open,high,low,52w_high,
+/// 52w_low,vol,...`
+///
+/// The timestamp is Beijing time. Sina gives neither an order book nor a
+/// turnover figure, so `amount` is estimated from volume and price.
+fn decode_us(fields: &[&str]) -> Decoded {
+    let [name, new, _, datetime, change, opening, high, low, _, _, vol, ..] = fields else {
+        return Err(short_line(fields));
+    };
+    let new = parse_field::<Price>("new", new)?;
+    let closing = new - parse_field::<Price>("change", change)?;
+    let vol = parse_field::<f64>("vol", vol)? as Vol;
+    let (date, time) = datetime.split_once(' ').unwrap_or((datetime, ""));
+    let data = BaseData {
+        opening: parse_field("opening", opening)?,
+        closing,
+        new,
+        hight: parse_field("high", high)?,
+        low: parse_field("low", low)?,
+        bid: 0.0,
+        ask: 0.0,
+        vol,
+        amount: vol as f32 * new,
+        date: date.to_string(),
+        time: time.to_string(),
+        rise_per: percent(new, closing),
+        bids: vec![],
+        asks: vec![],
+        currency: Currency::Usd,
+        instrument: Instrument::Equity,
+    };
+    Ok((name.to_string(), data))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() <= b.abs() * 1e-6, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn decodes_a_share_line() {
+        let line = "var hq_str_sh600519=\"贵州茅台,1505.000,1500.000,1520.000,1530.000,1498.000,\
+            1519.990,1520.000,2500000,3780000000.000,500,1519.990,300,1519.980,200,1519.970,\
+            100,1519.960,400,1519.950,600,1520.000,700,1520.010,800,1520.020,900,1520.030,\
+            1000,1520.040,2024-09-25,15:00:03,00\";";
+        let (code, name, data) = decode_from_string(line).unwrap();
+        assert_eq!((code.as_str(), name.as_str()), ("sh600519", "贵州茅台"));
+        assert_eq!(
+            (data.opening, data.closing, data.new),
+            (1505.0, 1500.0, 1520.0)
+        );
+        assert_eq!((data.hight, data.low), (1530.0, 1498.0));
+        assert_eq!((data.bid, data.ask), (1519.99, 1520.0));
+        assert_eq!(data.vol, 2_500_000);
+        assert_eq!(data.amount, 3_780_000_000.0);
+        assert_eq!(data.rise_per, 1.33);
+        // order book volumes are shares, shown in lots
+        assert_eq!(data.bids.len(), 5);
+        assert_eq!(data.bids[0], (5, 1519.99));
+        assert_eq!(data.asks[4], (10, 1520.04));
+        assert_eq!(
+            (data.date.as_str(), data.time.as_str()),
+            ("2024-09-25", "15:00:03")
+        );
+        assert_eq!(data.currency, Currency::Cny);
+    }
+
+    #[test]
+    fn decodes_hk_line() {
+        let line = "var hq_str_rt_hk00700=\"TENCENT,腾讯控股,412.000,410.000,425.000,411.000,\
+            420.000,10.000,2.439,419.800,420.000,4194304000,12345678,22.5,0.8,500.0,260.0,\
+            2024/09/25,16:08:19,30|0,N|Y|Y\";";
+        let (code, name, data) = decode_from_string(line).unwrap();
+        assert_eq!((code.as_str(), name.as_str()), ("hk00700", "腾讯控股"));
+        assert_eq!(
+            (data.opening, data.closing, data.new),
+            (412.0, 410.0, 420.0)
+        );
+        assert_eq!((data.hight, data.low), (425.0, 411.0));
+        assert_eq!((data.bid, data.ask), (419.8, 420.0));
+        assert_eq!(data.vol, 12_345_678);
+        assert_eq!(data.amount, 4_194_304_000.0);
+        assert_eq!(data.rise_per, 2.44);
+        assert!(data.bids.is_empty() && data.asks.is_empty());
+        assert_eq!(
+            (data.date.as_str(), data.time.as_str()),
+            ("2024-09-25", "16:08:19")
+        );
+        assert_eq!(data.currency, Currency::Hkd);
+    }
+
+    #[test]
+    fn decodes_us_line() {
+        let line = "var hq_str_gb_aapl=\"苹果,227.7800,0.50,2024-09-26 04:05:52,1.1300,\
+            227.3000,229.5200,227.3000,237.2300,164.0800,36636707,52288699,3463211400000,\
+            6.57,34.670000,0.00,0.00,0.00,0.00,15204000000,66,0.0000,0.00,0.00,,\
+            Sep 25 04:05PM EDT,227.7800,0,1,2024\";";
+        let (code, name, data) = decode_from_string(line).unwrap();
+        assert_eq!((code.as_str(), name.as_str()), ("gb_aapl", "苹果"));
+        assert_eq!(data.new, 227.78);
+        assert_close(data.closing, 226.65);
+        assert_eq!((data.opening, data.hight, data.low), (227.3, 229.52, 227.3));
+        assert_eq!(data.vol, 36_636_707);
+        // no turnover published, estimated from volume and price
+        assert_close(data.amount, 36_636_707.0 * 227.78);
+        assert_eq!(data.rise_per, 0.5);
+        assert_eq!(
+            (data.date.as_str(), data.time.as_str()),
+            ("2024-09-26", "04:05:52")
+        );
+        assert_eq!(data.currency, Currency::Usd);
+    }
+
+    #[test]
+    fn empty_payload_is_no_data() {
+        let body = "var hq_str_rt_hk00700=\"\";\nvar hq_str_gb_aapl=\"\";\n\
+            var hq_str_sh600519=\"\";\n";
+        let parsed = decode_response(body);
+        assert!(parsed.items.is_empty());
+        assert!(parsed.errors.is_empty());
+        assert_eq!(
+            decode_response("var hq_str_sh600519=\"x,1\";").errors.len(),
+            1
+        );
+    }
+}
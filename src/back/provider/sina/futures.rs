@@ -0,0 +1,285 @@
+use chrono::{Datelike, NaiveDateTime};
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+use super::{percent, short_line, Decoded};
+use crate::back::{
+    provider::{Parsed, ProviderError},
+    security::{Exchange, SecurityId},
+    stock::{
+        parse_day, parse_field, BaseData, Currency, FuturesData, Instrument, KLineScale, KlineItem,
+        Price, QuoteParseError, Vol,
+    },
+};
+
+pub(super) const KLINE_URL: &str = "https://stock2.finance.sina.com.cn/futures/api/json.php";
+
+/// `nf_` lines come in two layouts: CFFEX contracts start with the open
+/// price, commodity contracts with the contract name.
+///
+/// There is no decoder for `CFF_RE_` lines; codes given that way are asked
+/// for as `nf_` (see `SecurityId::parse`).
+pub(super) fn decode_futures(fields: &[&str]) -> Decoded {
+    match fields.first() {
+        Some(first) if first.parse::<f64>().is_ok() => decode_cffex(fields),
+        _ => decode_commodity(fields),
+    }
+}
+
+/// `open,high,low,new,vol,amount,open_interest,close,settlement,limit_up,
+/// limit_down,_,_,pre_settlement,pre_close,...,date(36),time(37),...,name(49)`
+fn decode_cffex(fields: &[&str]) -> Decoded {
+    if fields.len() < 50 {
+        return Err(short_line(fields));
+    }
+    let new = parse_field::<Price>("new", fields[3])?;
+    let pre_settlement = parse_field::<Price>("pre_settlement", fields[13])?;
+    let data = BaseData {
+        opening: parse_field("opening", fields[0])?,
+        hight: parse_field("high", fields[1])?,
+        low: parse_field("low", fields[2])?,
+        new,
+        vol: parse_field::<f64>("vol", fields[4])? as Vol,
+        amount: parse_field("amount", fields[5])?,
+        closing: pre_settlement,
+        bid: 0.0,
+        ask: 0.0,
+        date: fields[36].to_string(),
+        time: fields[37].to_string(),
+        rise_per: percent(new, pre_settlement),
+        bids: vec![],
+        asks: vec![],
+        currency: Currency::Cny,
+        instrument: Instrument::Futures(FuturesData {
+            open_interest: parse_field("open_interest", fields[6])?,
+            settlement: parse_field("settlement", fields[8])?,
+            pre_settlement,
+        }),
+    };
+    Ok((fields[49].to_string(), data))
+}
+
+/// `name,hhmmss,open,high,low,pre_close,bid,ask,new,settlement,pre_settlement,
+/// bid_vol,ask_vol,open_interest,vol,exchange,product,date`
+///
+/// Only the best bid and ask are published.
+fn decode_commodity(fields: &[&str]) -> Decoded {
+    let [name, time, opening, high, low, _, bid, ask, new, settlement, pre_settlement, bid_vol, ask_vol, open_interest, vol, _, _, date, ..] =
+        fields
+    else {
+        return Err(short_line(fields));
+    };
+    let new = parse_field::<Price>("new", new)?;
+    let pre_settlement = parse_field::<Price>("pre_settlement", pre_settlement)?;
+    let bid = parse_field::<Price>("bid", bid)?;
+    let ask = parse_field::<Price>("ask", ask)?;
+    let time = match time.len() {
+        6 => format!("{}:{}:{}", &time[0..2], &time[2..4], &time[4..6]),
+        _ => time.to_string(),
+    };
+    let data = BaseData {
+        opening: parse_field("opening", opening)?,
+        hight: parse_field("high", high)?,
+        low: parse_field("low", low)?,
+        new,
+        vol: parse_field::<f64>("vol", vol)? as Vol,
+        amount: 0.0,
+        closing: pre_settlement,
+        bid,
+        ask,
+        date: date.to_string(),
+        time,
+        rise_per: percent(new, pre_settlement),
+        bids: vec![(parse_field::<f64>("bid_vol", bid_vol)? as Vol, bid)],
+        asks: vec![(parse_field::<f64>("ask_vol", ask_vol)? as Vol, ask)],
+        currency: Currency::Cny,
+        instrument: Instrument::Futures(FuturesData {
+            open_interest: parse_field("open_interest", open_interest)?,
+            settlement: parse_field("settlement", settlement)?,
+            pre_settlement,
+        }),
+    };
+    Ok((name.to_string(), data))
+}
+
+/// Futures history lives on a different Sina service than stock klines, with
+/// separate endpoints for CFFEX and commodity contracts. Weekly and monthly
+/// bars are not offered, so they are built from daily ones.
+pub(super) fn klines(
+    client: &Client,
+    base_url: &str,
+    id: &SecurityId,
+    scale: &KLineScale,
+    datalen: u32,
+) -> Result<Parsed<KlineItem>, ProviderError> {
+    let service = match id.exchange {
+        Exchange::Cffex => "CffexFuturesService.getCffexFutures",
+        _ => "IndexService.getInnerFutures",
+    };
+    let endpoint = match scale {
+        KLineScale::Intraday => {
+            return Err(ProviderError::Unsupported(format!(
+                "sina intraday for {}",
+                id
+            )))
+        }
+        KLineScale::Munute5 => "MiniKLine5m",
+        KLineScale::Munute15 => "MiniKLine15m",
+        KLineScale::Munute30 => "MiniKLine30m",
+        KLineScale::Hour => "MiniKLine60m",
+        KLineScale::Day | KLineScale::Week | KLineScale::Month => "DailyKLine",
+    };
+    let rows = client
+        .get(format!("{base_url}/{service}{endpoint}?symbol={}", id.code))
+        .send()?
+        .error_for_status()?
+        .json::<Value>()?;
+
+    let mut parsed: Parsed<KlineItem> = rows
+        .as_array()
+        .map(|rows| rows.iter().map(decode_kline_row).collect())
+        .unwrap_or_default();
+    parsed.items.sort_by_key(|x| x.day);
+
+    parsed.items = match scale {
+        KLineScale::Week => aggregate(parsed.items, |d| {
+            let w = d.iso_week();
+            (w.year(), w.week())
+        }),
+        KLineScale::Month => aggregate(parsed.items, |d| (d.year(), d.month())),
+        _ => parsed.items,
+    };
+    let skip = parsed.items.len().saturating_sub(datalen as usize);
+    parsed.items.drain(..skip);
+    Ok(parsed)
+}
+
+/// `["2024-05-30 15:00:00", open, high, low, close, volume]`
+fn decode_kline_row(row: &Value) -> Result<KlineItem, QuoteParseError> {
+    let field = |i: usize, name: &'static str| {
+        row.get(i)
+            .and_then(Value::as_str)
+            .ok_or_else(|| QuoteParseError::Field {
+                field: name,
+                raw: row.to_string(),
+            })
+    };
+    Ok(KlineItem {
+        day: parse_day(field(0, "day")?)?,
+        open: parse_field("open", field(1, "open")?)?,
+        high: parse_field("high", field(2, "high")?)?,
+        low: parse_field("low", field(3, "low")?)?,
+        close: parse_field("close", field(4, "close")?)?,
+        volume: parse_field("volume", field(5, "volume")?)?,
+        amount: 0.0,
+    })
+}
+
+/// Merge consecutive bars sharing the same `period` key into one.
+fn aggregate<K: PartialEq>(
+    bars: Vec<KlineItem>,
+    period: impl Fn(&NaiveDateTime) -> K,
+) -> Vec<KlineItem> {
+    let mut out: Vec<KlineItem> = vec![];
+    let mut last_key = None;
+    for bar in bars {
+        let key = period(&bar.day);
+        match out.last_mut() {
+            Some(cur) if last_key.as_ref() == Some(&key) => {
+                cur.high = cur.high.max(bar.high);
+                cur.low = cur.low.min(bar.low);
+                cur.close = bar.close;
+                cur.volume += bar.volume;
+                cur.amount += bar.amount;
+                cur.day = bar.day;
+            }
+            _ => out.push(bar),
+        }
+        last_key = Some(key);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::decode_from_string;
+    use crate::back::{security::SecurityId, stock::Instrument};
+
+    fn futures(line: &str) -> (String, String, f32, f32, f64, f32, String, String) {
+        let (code, name, data) = decode_from_string(line).expect("a futures quote");
+        let Instrument::Futures(f) = data.instrument else {
+            panic!("not decoded as futures: {:?}", data.instrument);
+        };
+        (
+            code,
+            name,
+            data.new,
+            data.rise_per,
+            f.open_interest,
+            f.pre_settlement,
+            data.date,
+            data.time,
+        )
+    }
+
+    #[test]
+    fn decodes_cffex_layout() {
+        let mut fields = vec!["0"; 50];
+        fields[..9].copy_from_slice(&[
+            "3850.0",
+            "3880.2",
+            "3840.0",
+            "3870.4",
+            "52345",
+            "60512345678.0",
+            "150234",
+            "3870.4",
+            "3868.2",
+        ]);
+        fields[13] = "3850.0";
+        fields[36] = "2024-06-03";
+        fields[37] = "15:00:00";
+        fields[49] = "沪深300指数期货2406";
+        let line = format!("var hq_str_nf_IF2406=\"{}\";", fields.join(","));
+        assert_eq!(
+            futures(&line),
+            (
+                "nf_IF2406".to_string(),
+                "沪深300指数期货2406".to_string(),
+                3870.4,
+                0.53,
+                150234.0,
+                3850.0,
+                "2024-06-03".to_string(),
+                "15:00:00".to_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn decodes_commodity_layout() {
+        let line = "var hq_str_nf_RB2410=\"螺纹钢2410,150000,3600.000,3650.000,3590.000,\
+            3610.000,3630.000,3631.000,3630.000,3625.000,3605.000,120,85,1823456.000,\
+            923456.000,沪,螺纹钢,2024-06-03,0\";";
+        assert_eq!(
+            futures(line),
+            (
+                "nf_RB2410".to_string(),
+                "螺纹钢2410".to_string(),
+                3630.0,
+                0.69,
+                1823456.0,
+                3605.0,
+                "2024-06-03".to_string(),
+                "15:00:00".to_string(),
+            )
+        );
+    }
+
+    #[test]
+    fn cff_re_is_an_alias_for_nf() {
+        let id = SecurityId::parse("CFF_RE_IF2406").unwrap();
+        assert_eq!(id, SecurityId::parse("nf_IF2406").unwrap());
+        assert_eq!(id.symbol(), "nf_IF2406");
+    }
+}
@@ -0,0 +1,436 @@
+use chrono::{NaiveDate, NaiveDateTime, TimeDelta};
+use reqwest::blocking::Client;
+use serde_json::Value;
+
+use super::{decode_body, require_quotes, Parsed, ProviderError, Quote, QuoteProvider};
+use crate::back::security::{Exchange, SecurityId};
+use crate::back::stock::{
+    parse_field, BaseData, Currency, Instrument, KLineScale, KlineItem, Price, QuoteParseError, Vol,
+};
+
+const BASE_URL: &str = "http://qt.gtimg.cn";
+const MINUTE_KLINE_URL: &str = "https://ifzq.gtimg.cn/appstock/app/kline/mkline";
+const DAY_KLINE_URL: &str = "https://web.ifzq.gtimg.cn/appstock/app/fqkline/get";
+const INTRADAY_URL: &str = "https://web.ifzq.gtimg.cn/appstock/app/minute/query";
+
+/// Quotes from qt.gtimg.cn, used as a fallback when Sina refuses us.
+#[derive(Debug, Clone)]
+pub struct TencentProvider {
+    client: Client,
+    base_url: String,
+}
+
+impl Default for TencentProvider {
+    fn default() -> Self {
+        Self::with_url(BASE_URL)
+    }
+}
+
+impl TencentProvider {
+    pub fn with_url(base_url: &str) -> Self {
+        Self {
+            client: Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+        }
+    }
+
+    fn minute_klines(
+        &self,
+        code: &str,
+        period: &str,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        let body = self
+            .client
+            .get(format!(
+                "{MINUTE_KLINE_URL}?param={code},{period},,{datalen}"
+            ))
+            .send()?
+            .error_for_status()?
+            .json::<Value>()?;
+        let rows = &body["data"][code][period];
+        Ok(decode_kline_rows(rows, "%Y%m%d%H%M", lot_size(code)))
+    }
+
+    /// Bars up to `end` (`YYYY-MM-DD`), or the latest when it is empty.
+    fn day_klines(
+        &self,
+        code: &str,
+        period: &str,
+        end: &str,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        let body = self
+            .client
+            .get(format!(
+                "{DAY_KLINE_URL}?param={code},{period},,{end},{datalen},qfq"
+            ))
+            .send()?
+            .error_for_status()?
+            .json::<Value>()?;
+        let data = &body["data"][code];
+        // adjusted series are keyed `qfqday`, indexes and funds only have `day`
+        let rows = match &data[format!("qfq{period}").as_str()] {
+            Value::Null => &data[period],
+            rows => rows,
+        };
+        Ok(decode_kline_rows(rows, "%Y-%m-%d", lot_size(code)))
+    }
+
+    /// The current session minute by minute, one flat bar per minute.
+    fn intraday(&self, code: &str) -> Result<Parsed<KlineItem>, ProviderError> {
+        let body = self
+            .client
+            .get(format!("{INTRADAY_URL}?code={code}"))
+            .send()?
+            .error_for_status()?
+            .json::<Value>()?;
+        let data = &body["data"][code]["data"];
+        let date = data["date"].as_str().unwrap_or_default();
+        Ok(decode_intraday_rows(&data["data"], date, lot_size(code)))
+    }
+}
+
+impl QuoteProvider for TencentProvider {
+    fn name(&self) -> &str {
+        "tencent"
+    }
+
+    fn fetch(&self, codes: &[SecurityId]) -> Result<Parsed<Quote>, ProviderError> {
+        // Tencent has no futures quotes under these symbols
+        let codes = codes
+            .iter()
+            .filter(|id| !id.is_futures())
+            .cloned()
+            .collect::<Vec<SecurityId>>();
+        if codes.is_empty() {
+            return Ok(Parsed::default());
+        }
+        let list = codes
+            .iter()
+            .map(tencent_symbol)
+            .collect::<Vec<String>>()
+            .join(",");
+        let url = format!("{}/q={}", self.base_url, list);
+
+        let str = decode_body(self.client.get(&url).send()?)?;
+
+        let stocks = str
+            .split(';')
+            .map(str::trim)
+            .filter(|x| !x.is_empty())
+            .map(decode_from_string)
+            .collect();
+        require_quotes(&codes, stocks)
+    }
+
+    fn klines(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        if code.is_futures() {
+            return Err(ProviderError::Unsupported(format!(
+                "tencent klines for {}",
+                code
+            )));
+        }
+        let code = &tencent_symbol(code);
+        match scale {
+            KLineScale::Intraday => self.intraday(code),
+            KLineScale::Munute5 => self.minute_klines(code, "m5", datalen),
+            KLineScale::Munute15 => self.minute_klines(code, "m15", datalen),
+            KLineScale::Munute30 => self.minute_klines(code, "m30", datalen),
+            KLineScale::Hour => self.minute_klines(code, "m60", datalen),
+            KLineScale::Day => self.day_klines(code, "day", "", datalen),
+            KLineScale::Week => self.day_klines(code, "week", "", datalen),
+            KLineScale::Month => self.day_klines(code, "month", "", datalen),
+        }
+    }
+
+    /// Daily and longer bars take an end date; minute bars only come newest
+    /// first.
+    fn klines_before(
+        &self,
+        code: &SecurityId,
+        scale: &KLineScale,
+        before: NaiveDateTime,
+        datalen: u32,
+    ) -> Result<Parsed<KlineItem>, ProviderError> {
+        if code.is_futures() {
+            return Err(ProviderError::Unsupported(format!(
+                "tencent klines for {}",
+                code
+            )));
+        }
+        let period = match scale {
+            KLineScale::Day => "day",
+            KLineScale::Week => "week",
+            KLineScale::Month => "month",
+            _ => {
+                return Err(ProviderError::Unsupported(format!(
+                    "tencent {:?} klines by end date for {}",
+                    scale, code
+                )))
+            }
+        };
+        let end = (before - TimeDelta::days(1)).format("%Y-%m-%d").to_string();
+        let mut parsed = self.day_klines(&tencent_symbol(code), period, &end, datalen)?;
+        parsed.items.retain(|x| x.day < before);
+        Ok(parsed)
+    }
+}
+
+/// Shares per volume unit of a Tencent symbol: A-share volumes are in lots,
+/// Hong Kong and US ones in shares.
+fn lot_size(code: &str) -> f64 {
+    if code.starts_with("us") || code.starts_with("hk") {
+        1.0
+    } else {
+        100.0
+    }
+}
+
+/// Rows look like `["2024-09-25", open, close, high, low, volume, ...]`,
+/// volume in units of `lot` shares.
+fn decode_kline_rows(rows: &Value, day_format: &str, lot: f64) -> Parsed<KlineItem> {
+    let Some(rows) = rows.as_array() else {
+        return Parsed::default();
+    };
+    rows.iter()
+        .map(|row| {
+            let field = |i: usize, name: &'static str| {
+                row.get(i)
+                    .and_then(Value::as_str)
+                    .ok_or_else(|| QuoteParseError::Field {
+                        field: name,
+                        raw: row.to_string(),
+                    })
+            };
+            let raw_day = field(0, "day")?;
+            let day = NaiveDateTime::parse_from_str(raw_day, day_format)
+                .or_else(|_| {
+                    NaiveDate::parse_from_str(raw_day, day_format).map(NaiveDateTime::from)
+                })
+                .map_err(|_| QuoteParseError::Field {
+                    field: "day",
+                    raw: raw_day.to_string(),
+                })?;
+            Ok(KlineItem {
+                day,
+                open: parse_field("open", field(1, "open")?)?,
+                close: parse_field("close", field(2, "close")?)?,
+                high: parse_field("high", field(3, "high")?)?,
+                low: parse_field("low", field(4, "low")?)?,
+                volume: parse_field::<f64>("volume", field(5, "volume")?)? * lot,
+                amount: 0.0,
+            })
+        })
+        .collect()
+}
+
+/// Rows look like `"0931 1452.00 2530 367236000.00"`: time, price and the
+/// session's running volume and amount, turned into per-minute figures.
+fn decode_intraday_rows(rows: &Value, date: &str, lot: f64) -> Parsed<KlineItem> {
+    let Some(rows) = rows.as_array() else {
+        return Parsed::default();
+    };
+    let mut running = (0.0, 0.0);
+    rows.iter()
+        .map(|row| {
+            let raw = row.as_str().unwrap_or_default();
+            let fields = raw.split_whitespace().collect::<Vec<&str>>();
+            if fields.len() < 4 {
+                return Err(QuoteParseError::Layout {
+                    raw: raw.to_string(),
+                });
+            }
+            let day = NaiveDateTime::parse_from_str(&format!("{date}{}", fields[0]), "%Y%m%d%H%M")
+                .map_err(|_| QuoteParseError::Field {
+                    field: "minute",
+                    raw: raw.to_string(),
+                })?;
+            let price = parse_field::<f64>("price", fields[1])?;
+            let volume = parse_field::<f64>("volume", fields[2])? * lot;
+            let amount = parse_field::<f64>("amount", fields[3])?;
+            let (prev_volume, prev_amount) = std::mem::replace(&mut running, (volume, amount));
+            Ok(KlineItem {
+                day,
+                open: price,
+                high: price,
+                low: price,
+                close: price,
+                volume: volume - prev_volume,
+                amount: amount - prev_amount,
+            })
+        })
+        .collect()
+}
+
+/// How Tencent names `id`: US tickers are `usAAPL` rather than `gb_aapl`.
+fn tencent_symbol(id: &SecurityId) -> String {
+    match id.exchange {
+        Exchange::Us => format!("us{}", id.code.to_ascii_uppercase()),
+        _ => id.symbol(),
+    }
+}
+
+/// Decode one `v_sh600519="1~贵州茅台~600519~..."` line.
+///
+/// Hong Kong and US lines share the layout. For A-shares volumes are reported
+/// in lots and the amount in units of 10k yuan, so both are scaled to match
+/// what Sina gives us.
+pub(crate) fn decode_from_string(stock_string: &str) -> Result<Quote, QuoteParseError> {
+    let layout = || QuoteParseError::Layout {
+        raw: stock_string.to_string(),
+    };
+    let (var, body) = stock_string.split_once("=\"").ok_or_else(layout)?;
+    let code = var.trim().trim_start_matches("v_");
+    let (code, currency) = match code.strip_prefix("us") {
+        Some(ticker) => (format!("gb_{}", ticker.to_ascii_lowercase()), Currency::Usd),
+        None if code.starts_with("hk") => (code.to_string(), Currency::Hkd),
+        None => (code.to_string(), Currency::Cny),
+    };
+    let fields: Vec<&str> = body.trim_end_matches('"').split('~').collect();
+    if fields.len() < 38 {
+        return Err(layout());
+    }
+    let lots = |name: &'static str, i: usize| parse_field::<f64>(name, fields[i]).map(|x| x as Vol);
+
+    let new = parse_field::<Price>("new", fields[3])?;
+    let closing = parse_field::<Price>("closing", fields[4])?;
+    let percent = if closing > 0.0 {
+        ((new - closing) / closing * 10000.0).round() / 100.0
+    } else {
+        0.0
+    };
+
+    // bid1..5 at 9..18 and ask1..5 at 19..28, each as (price, lots)
+    let ladder = |start: usize| -> Result<Vec<(Vol, Price)>, QuoteParseError> {
+        (0..5)
+            .map(|i| {
+                Ok((
+                    lots("order volume", start + i * 2 + 1)?,
+                    parse_field("order price", fields[start + i * 2])?,
+                ))
+            })
+            .collect()
+    };
+    let bids = ladder(9)?;
+    let asks = ladder(19)?;
+
+    let (vol, amount) = match currency {
+        Currency::Cny => (
+            lots("vol", 36)? * 100,
+            parse_field::<f32>("amount", fields[37])? * 10000.0,
+        ),
+        _ => (lots("vol", 6)?, parse_field::<f32>("amount", fields[37])?),
+    };
+
+    // 20240925150003, 2024/09/25 16:08:19 or 2024-09-25 16:00:01
+    let stamp = fields[30];
+    let (date, time) = match NaiveDateTime::parse_from_str(stamp, "%Y%m%d%H%M%S")
+        .or_else(|_| NaiveDateTime::parse_from_str(stamp, "%Y/%m/%d %H:%M:%S"))
+        .or_else(|_| NaiveDateTime::parse_from_str(stamp, "%Y-%m-%d %H:%M:%S"))
+    {
+        Ok(t) => (
+            t.format("%Y-%m-%d").to_string(),
+            t.format("%H:%M:%S").to_string(),
+        ),
+        Err(_) => (String::new(), String::new()),
+    };
+
+    let data = BaseData {
+        date,
+        time,
+        opening: parse_field("opening", fields[5])?,
+        closing,
+        hight: parse_field("high", fields[33])?,
+        low: parse_field("low", fields[34])?,
+        vol,
+        amount,
+        bid: bids[0].1,
+        ask: asks[0].1,
+        new,
+        rise_per: percent,
+        bids,
+        asks,
+        currency,
+        instrument: Instrument::Equity,
+    };
+    Ok((code, fields[1].to_string(), data))
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    const A_SHARE: &str = "v_sh600519=\"1~贵州茅台~600519~1520.00~1500.00~1505.00~25000~12000~\
+        13000~1519.99~5~1519.98~3~1519.97~2~1519.96~1~1519.95~4~1520.00~6~1520.01~7~1520.02~8~\
+        1520.03~9~1520.04~10~~20240925150003~20.00~1.33~1530.00~1498.00~\
+        1520.00/25000/378000000~25000~37800~0.20~20.0\"";
+
+    const HK: &str = "v_hk00700=\"100~腾讯控股~00700~420.000~410.000~412.000~12345678~0~0~\
+        419.800~1000~419.600~2000~419.400~3000~419.200~4000~419.000~5000~420.000~1100~\
+        420.200~1200~420.400~1300~420.600~1400~420.800~1500~~2024/09/25 16:08:19~10.000~2.44~\
+        425.000~411.000~420.000/12345678/4194304000~12345678~4194304000~0.13~22.5\"";
+
+    #[test]
+    fn decodes_a_share_line() {
+        let (code, name, data) = decode_from_string(A_SHARE).unwrap();
+        assert_eq!((code.as_str(), name.as_str()), ("sh600519", "贵州茅台"));
+        assert_eq!(
+            (data.new, data.closing, data.opening),
+            (1520.0, 1500.0, 1505.0)
+        );
+        assert_eq!((data.hight, data.low), (1530.0, 1498.0));
+        assert_eq!(data.rise_per, 1.33);
+        // lots and units of 10k yuan scaled to shares and yuan
+        assert_eq!(data.vol, 2_500_000);
+        assert_eq!(data.amount, 378_000_000.0);
+        assert_eq!(data.bids.len(), 5);
+        assert_eq!(data.bids[0], (5, 1519.99));
+        assert_eq!(data.asks[4], (10, 1520.04));
+        assert_eq!((data.bid, data.ask), (1519.99, 1520.0));
+        assert_eq!(
+            (data.date.as_str(), data.time.as_str()),
+            ("2024-09-25", "15:00:03")
+        );
+        assert_eq!(data.currency, Currency::Cny);
+    }
+
+    #[test]
+    fn decodes_hk_line() {
+        let (code, name, data) = decode_from_string(HK).unwrap();
+        assert_eq!((code.as_str(), name.as_str()), ("hk00700", "腾讯控股"));
+        assert_eq!((data.new, data.closing), (420.0, 410.0));
+        assert_eq!(data.rise_per, 2.44);
+        // already in shares and dollars
+        assert_eq!(data.vol, 12_345_678);
+        assert_eq!(data.amount, 4_194_304_000.0);
+        assert_eq!(data.bids[0], (1000, 419.8));
+        assert_eq!(data.asks[0], (1100, 420.0));
+        assert_eq!(
+            (data.date.as_str(), data.time.as_str()),
+            ("2024-09-25", "16:08:19")
+        );
+        assert_eq!(data.currency, Currency::Hkd);
+    }
+
+    #[test]
+    fn rejects_short_line() {
+        assert!(decode_from_string("v_sh600519=\"1~贵州茅台~600519\"").is_err());
+    }
+
+    #[test]
+    fn kline_volume_in_lots_only_for_a_shares() {
+        let rows = json!([["2024-09-25", "10.0", "10.5", "10.8", "9.9", "1234.000"]]);
+        let volume = |code| decode_kline_rows(&rows, "%Y-%m-%d", lot_size(code)).items[0].volume;
+        assert_eq!(volume("sh600000"), 123_400.0);
+        assert_eq!(volume("hk00700"), 1234.0);
+        assert_eq!(volume("usAAPL"), 1234.0);
+    }
+}
@@ -0,0 +1,383 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
+use once_cell::sync::Lazy;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::stock::Currency;
+
+static REG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?i)(sh|sz|bj)?(\d{6})$").unwrap());
+static HK_REG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?i)(?:(?:rt_)?hk)?(\d{5})$").unwrap());
+static US_REG: Lazy<Regex> = Lazy::new(|| Regex::new(r"^(?i)gb_([a-z][a-z0-9.$]*)$").unwrap());
+/// `CFF_RE_` is Sina's older CFFEX prefix. It is read only as an alias for
+/// `nf_`: quotes are always requested, and decoded, in the `nf_` layout.
+static FUTURES_REG: Lazy<Regex> =
+    Lazy::new(|| Regex::new(r"^(?i)(?:nf_|CFF_RE_)([a-z]{1,2})(\d{0,4})$").unwrap());
+
+/// Products listed on the China Financial Futures Exchange.
+const CFFEX_PRODUCTS: [&str; 8] = ["IF", "IH", "IC", "IM", "T", "TF", "TS", "TL"];
+/// CFFEX treasury bond futures.
+const BOND_FUTURES: [&str; 4] = ["T", "TF", "TS", "TL"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Exchange {
+    /// Shanghai Stock Exchange
+    Sh,
+    /// Shenzhen Stock Exchange
+    Sz,
+    /// Beijing Stock Exchange
+    Bj,
+    /// Hong Kong Exchanges
+    Hk,
+    /// NYSE / NASDAQ, as a single market
+    Us,
+    /// China Financial Futures Exchange
+    Cffex,
+    /// SHFE, DCE, CZCE, INE and GFEX, which Sina quotes alike
+    Commodity,
+}
+
+impl Exchange {
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Exchange::Sh => "sh",
+            Exchange::Sz => "sz",
+            Exchange::Bj => "bj",
+            Exchange::Hk => "hk",
+            Exchange::Us => "gb_",
+            Exchange::Cffex | Exchange::Commodity => "nf_",
+        }
+    }
+
+    pub fn currency(&self) -> Currency {
+        match self {
+            Exchange::Sh | Exchange::Sz | Exchange::Bj | Exchange::Cffex | Exchange::Commodity => {
+                Currency::Cny
+            }
+            Exchange::Hk => Currency::Hkd,
+            Exchange::Us => Currency::Usd,
+        }
+    }
+
+    fn from_prefix(prefix: &str) -> Option<Self> {
+        match prefix.to_ascii_lowercase().as_str() {
+            "sh" => Some(Exchange::Sh),
+            "sz" => Some(Exchange::Sz),
+            "bj" => Some(Exchange::Bj),
+            _ => None,
+        }
+    }
+
+    /// Which exchange a bare six digit code most likely belongs to.
+    ///
+    /// `000001` is both Ping An Bank (sz) and the SSE Composite (sh); bare
+    /// codes resolve to the stock, the index needs an explicit `sh` prefix.
+    /// Beijing's `920` range would otherwise read as a Shanghai B share.
+    fn infer(code: &str) -> Option<Self> {
+        match &code[..1] {
+            "9" if code.starts_with("920") => Some(Exchange::Bj),
+            "5" | "6" | "9" => Some(Exchange::Sh),
+            "0" | "2" | "3" => Some(Exchange::Sz),
+            "4" | "8" => Some(Exchange::Bj),
+            "1" => match &code[..2] {
+                "10" | "11" => Some(Exchange::Sh),
+                _ => Some(Exchange::Sz),
+            },
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Board {
+    /// Main board A shares
+    Main,
+    /// STAR market (sh 688/689)
+    Star,
+    /// ChiNext (sz 300/301)
+    ChiNext,
+    /// Beijing Stock Exchange listings
+    Beijing,
+    /// B shares (sh 900, sz 200)
+    BShare,
+    Index,
+    /// ETFs, LOFs and closed-end funds
+    Fund,
+    /// Treasury, corporate and convertible bonds
+    Bond,
+    /// HKEX main board
+    HkMain,
+    /// HKEX GEM (08xxx)
+    HkGem,
+    /// US listed stocks and ETFs
+    Us,
+    /// CFFEX stock index futures
+    IndexFuture,
+    /// CFFEX treasury bond futures
+    BondFuture,
+    /// Commodity futures
+    CommodityFuture,
+}
+
+impl Board {
+    fn classify(exchange: Exchange, code: &str) -> Option<Self> {
+        let p3 = code.get(..3).unwrap_or_default();
+        let p2 = code.get(..2).unwrap_or_default();
+        match exchange {
+            Exchange::Sh => match p3 {
+                "600" | "601" | "603" | "605" => Some(Board::Main),
+                "688" | "689" => Some(Board::Star),
+                "900" => Some(Board::BShare),
+                "000" => Some(Board::Index),
+                _ if p2 == "50" || p2 == "51" || p2 == "52" || p2 == "56" || p2 == "58" => {
+                    Some(Board::Fund)
+                }
+                _ if p2 == "01" || p2 == "02" || p2 == "10" || p2 == "11" || p2 == "12" => {
+                    Some(Board::Bond)
+                }
+                _ => None,
+            },
+            Exchange::Sz => match p3 {
+                "000" | "001" | "002" | "003" => Some(Board::Main),
+                "300" | "301" => Some(Board::ChiNext),
+                "200" => Some(Board::BShare),
+                "399" => Some(Board::Index),
+                _ if p2 == "15" || p2 == "16" || p2 == "18" => Some(Board::Fund),
+                _ if p2 == "10" || p2 == "11" || p2 == "12" => Some(Board::Bond),
+                _ => None,
+            },
+            Exchange::Bj => match p3 {
+                "899" => Some(Board::Index),
+                "920" => Some(Board::Beijing),
+                _ if p2 == "43" || p2 == "83" || p2 == "87" || p2 == "88" => Some(Board::Beijing),
+                _ => None,
+            },
+            Exchange::Hk => match p2 {
+                "08" => Some(Board::HkGem),
+                _ => Some(Board::HkMain),
+            },
+            Exchange::Us => Some(Board::Us),
+            Exchange::Cffex => {
+                let product = code.trim_end_matches(|c: char| c.is_ascii_digit());
+                if BOND_FUTURES.contains(&product) {
+                    Some(Board::BondFuture)
+                } else {
+                    Some(Board::IndexFuture)
+                }
+            }
+            Exchange::Commodity => Some(Board::CommodityFuture),
+        }
+    }
+}
+
+/// A validated instrument code, e.g. `sh600519`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct SecurityId {
+    pub exchange: Exchange,
+    pub code: String,
+    pub board: Board,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SecurityParseError {
+    /// Not a code in any format we understand.
+    Format(String),
+    /// Well formed, but no board on that exchange uses this range.
+    UnknownRange(String),
+}
+
+impl Display for SecurityParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecurityParseError::Format(raw) => write!(f, "not a stock code: {:?}", raw),
+            SecurityParseError::UnknownRange(raw) => write!(f, "unknown code range: {:?}", raw),
+        }
+    }
+}
+
+impl std::error::Error for SecurityParseError {}
+
+impl SecurityId {
+    /// Parse `sh600519`, `SZ000001`, `bj430047` or a bare `600519`, Hong Kong
+    /// codes as `hk00700` or a bare `00700`, US tickers as `gb_aapl`, and
+    /// futures as `nf_IF0`, `nf_RB2410` or `CFF_RE_IF2406`.
+    pub fn parse(raw: &str) -> Result<Self, SecurityParseError> {
+        let raw = raw.trim();
+        if let Some(caps) = HK_REG.captures(raw) {
+            return Ok(Self::new(Exchange::Hk, &caps[1]));
+        }
+        if let Some(caps) = US_REG.captures(raw) {
+            return Ok(Self::new(Exchange::Us, &caps[1].to_ascii_lowercase()));
+        }
+        if let Some(caps) = FUTURES_REG.captures(raw) {
+            let product = caps[1].to_ascii_uppercase();
+            let exchange = if CFFEX_PRODUCTS.contains(&product.as_str()) {
+                Exchange::Cffex
+            } else {
+                Exchange::Commodity
+            };
+            return Ok(Self::new(exchange, &format!("{}{}", product, &caps[2])));
+        }
+        let caps = REG
+            .captures(raw)
+            .ok_or_else(|| SecurityParseError::Format(raw.to_string()))?;
+        let code = caps[2].to_string();
+        let exchange = match caps.get(1) {
+            Some(prefix) => Exchange::from_prefix(prefix.as_str()),
+            None => Exchange::infer(&code),
+        }
+        .ok_or_else(|| SecurityParseError::UnknownRange(raw.to_string()))?;
+        let board = Board::classify(exchange, &code)
+            .ok_or_else(|| SecurityParseError::UnknownRange(raw.to_string()))?;
+        Ok(Self {
+            exchange,
+            code,
+            board,
+        })
+    }
+
+    /// For markets where every code maps to a board.
+    fn new(exchange: Exchange, code: &str) -> Self {
+        let board =
+            Board::classify(exchange, code).expect("hk, us and futures codes always have a board");
+        Self {
+            exchange,
+            code: code.to_string(),
+            board,
+        }
+    }
+
+    /// Shanghai, Shenzhen or Beijing.
+    pub fn is_a_share(&self) -> bool {
+        matches!(self.exchange, Exchange::Sh | Exchange::Sz | Exchange::Bj)
+    }
+
+    pub fn is_futures(&self) -> bool {
+        matches!(self.exchange, Exchange::Cffex | Exchange::Commodity)
+    }
+
+    /// The futures product, e.g. `IF` for `nf_IF2406`.
+    pub fn product(&self) -> &str {
+        self.code.trim_end_matches(|c: char| c.is_ascii_digit())
+    }
+
+    /// The code as quote vendors expect it, e.g. `sh600519`.
+    pub fn symbol(&self) -> String {
+        format!("{}{}", self.exchange.prefix(), self.code)
+    }
+}
+
+impl Display for SecurityId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}{}", self.exchange.prefix(), self.code)
+    }
+}
+
+impl FromStr for SecurityId {
+    type Err = SecurityParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<String> for SecurityId {
+    type Error = SecurityParseError;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        Self::parse(&value)
+    }
+}
+
+impl From<SecurityId> for String {
+    fn from(value: SecurityId) -> Self {
+        value.symbol()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_codes_to_exchange_and_board() {
+        let cases = [
+            ("sh600519", Exchange::Sh, "600519", Board::Main),
+            ("600519", Exchange::Sh, "600519", Board::Main),
+            (" SH688981 ", Exchange::Sh, "688981", Board::Star),
+            ("510300", Exchange::Sh, "510300", Board::Fund),
+            ("113050", Exchange::Sh, "113050", Board::Bond),
+            ("900901", Exchange::Sh, "900901", Board::BShare),
+            ("sh000001", Exchange::Sh, "000001", Board::Index),
+            ("000001", Exchange::Sz, "000001", Board::Main),
+            ("300750", Exchange::Sz, "300750", Board::ChiNext),
+            ("399001", Exchange::Sz, "399001", Board::Index),
+            ("159915", Exchange::Sz, "159915", Board::Fund),
+            ("123100", Exchange::Sz, "123100", Board::Bond),
+            ("200002", Exchange::Sz, "200002", Board::BShare),
+            ("bj430047", Exchange::Bj, "430047", Board::Beijing),
+            ("830799", Exchange::Bj, "830799", Board::Beijing),
+            ("920118", Exchange::Bj, "920118", Board::Beijing),
+            ("bj899050", Exchange::Bj, "899050", Board::Index),
+            ("hk00700", Exchange::Hk, "00700", Board::HkMain),
+            ("00700", Exchange::Hk, "00700", Board::HkMain),
+            ("rt_hk08001", Exchange::Hk, "08001", Board::HkGem),
+            ("gb_AAPL", Exchange::Us, "aapl", Board::Us),
+            ("gb_brk.b", Exchange::Us, "brk.b", Board::Us),
+            ("nf_IF0", Exchange::Cffex, "IF0", Board::IndexFuture),
+            ("CFF_RE_T2412", Exchange::Cffex, "T2412", Board::BondFuture),
+            (
+                "nf_rb2410",
+                Exchange::Commodity,
+                "RB2410",
+                Board::CommodityFuture,
+            ),
+        ];
+        for (raw, exchange, code, board) in cases {
+            let id = SecurityId::parse(raw).unwrap_or_else(|e| panic!("{}: {}", raw, e));
+            assert_eq!(
+                (id.exchange, id.code.as_str(), id.board),
+                (exchange, code, board),
+                "{}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_malformed_and_unknown_ranges() {
+        let cases = [
+            ("", SecurityParseError::Format("".into())),
+            ("abc", SecurityParseError::Format("abc".into())),
+            ("6005190", SecurityParseError::Format("6005190".into())),
+            ("xx600519", SecurityParseError::Format("xx600519".into())),
+            ("700000", SecurityParseError::UnknownRange("700000".into())),
+            (
+                "sz600000",
+                SecurityParseError::UnknownRange("sz600000".into()),
+            ),
+            (
+                "sh300750",
+                SecurityParseError::UnknownRange("sh300750".into()),
+            ),
+        ];
+        for (raw, err) in cases {
+            assert_eq!(SecurityId::parse(raw), Err(err), "{}", raw);
+        }
+    }
+
+    #[test]
+    fn symbol_round_trips() {
+        for raw in [
+            "sh600519", "sz000001", "bj920118", "hk00700", "gb_aapl", "nf_IF0",
+        ] {
+            let id = SecurityId::parse(raw).unwrap();
+            assert_eq!(id.symbol(), raw);
+            assert_eq!(SecurityId::parse(&id.symbol()), Ok(id));
+        }
+    }
+}
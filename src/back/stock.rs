@@ -1,13 +1,12 @@
+use std::{
+    fmt::{self, Display},
+    str::FromStr,
+};
+
 use chrono::{NaiveDate, NaiveDateTime};
-use once_cell::sync::Lazy;
-use regex::Regex;
 use serde::{Deserialize, Serialize};
 
-const BASE_URL: &str = "http://hq.sinajs.cn";
-
-const MIN_LEN: usize = "var hq_str_cc000000=\"\";".len();
-
-static REG: Lazy<Regex> = Lazy::new(|| Regex::new(r"...").unwrap());
+use super::security::SecurityId;
 
 pub type Vol = u64;
 pub type Price = f32;
@@ -22,9 +21,16 @@ pub struct Stock {
     pub klines: Vec<KlineItem>,
 
     pub show_klines_viewport: bool,
+    /// An older page of klines was requested and has not arrived yet.
+    pub history_loading: bool,
+    /// Nothing older than what is loaded can be had, e.g. the provider's
+    /// bar cap was reached.
+    pub history_exhausted: bool,
+    /// Bars prepended since the chart last drew, to keep its view in place.
+    pub view_shift: usize,
 }
 
-#[derive(Clone, Default, Debug)]
+#[derive(Clone, Default, Debug, Serialize)]
 pub struct BaseData {
     pub date: String,
     pub time: String,
@@ -40,6 +46,41 @@ pub struct BaseData {
     pub rise_per: f32,
     pub bids: Vec<(Vol, Price)>,
     pub asks: Vec<(Vol, Price)>,
+    pub currency: Currency,
+    pub instrument: Instrument,
+}
+
+/// Quote fields that only some kinds of instrument carry.
+#[derive(Clone, Default, Debug, PartialEq, Serialize)]
+pub enum Instrument {
+    #[default]
+    Equity,
+    Futures(FuturesData),
+}
+
+#[derive(Clone, Default, Debug, PartialEq, Serialize)]
+pub struct FuturesData {
+    pub open_interest: f64,
+    pub settlement: Price,
+    pub pre_settlement: Price,
+}
+
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize)]
+pub enum Currency {
+    #[default]
+    Cny,
+    Hkd,
+    Usd,
+}
+
+impl Currency {
+    pub fn code(&self) -> &'static str {
+        match self {
+            Currency::Cny => "CNY",
+            Currency::Hkd => "HKD",
+            Currency::Usd => "USD",
+        }
+    }
 }
 
 // {
@@ -73,8 +114,10 @@ pub struct KlineItem {
     pub amount: f64,
 }
 
-#[derive(Default, Debug, Clone, PartialEq, Eq)]
+#[derive(Default, Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
 pub enum KLineScale {
+    /// One-minute price and volume of the current session (分时).
+    Intraday,
     Munute5,
     #[default]
     Munute15,
@@ -86,45 +129,108 @@ pub enum KLineScale {
 }
 
 impl KLineScale {
+    pub const ALL: [KLineScale; 8] = [
+        KLineScale::Intraday,
+        KLineScale::Munute5,
+        KLineScale::Munute15,
+        KLineScale::Munute30,
+        KLineScale::Hour,
+        KLineScale::Day,
+        KLineScale::Week,
+        KLineScale::Month,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            KLineScale::Intraday => "intraday",
+            KLineScale::Munute5 => "5",
+            KLineScale::Munute15 => "15",
+            KLineScale::Munute30 => "30",
+            KLineScale::Hour => "60",
+            KLineScale::Day => "day",
+            KLineScale::Week => "week",
+            KLineScale::Month => "month",
+        }
+    }
+
+    /// Sina's `scale` parameter: trading minutes per bar, 240 a day.
     pub fn to_usize(&self) -> usize {
         match self {
+            KLineScale::Intraday => 1,
             KLineScale::Munute5 => 5,
             KLineScale::Munute15 => 15,
             KLineScale::Munute30 => 30,
             KLineScale::Hour => 60,
             KLineScale::Day => 240,
             KLineScale::Week => 1200,
-            KLineScale::Month => 60,
+            KLineScale::Month => 7200,
         }
     }
 }
 
-impl From<KlineItemD> for KlineItem {
-    fn from(item: KlineItemD) -> Self {
-        let day = match NaiveDateTime::parse_from_str(item.day.as_str(), "%Y-%m-%d %H:%M:%S") {
-            Ok(d) => d,
-            Err(_) => NaiveDate::parse_from_str(item.day.as_str(), "%Y-%m-%d")
-                .unwrap()
-                .into(),
-        };
+impl TryFrom<KlineItemD> for KlineItem {
+    type Error = QuoteParseError;
 
-        Self {
+    fn try_from(item: KlineItemD) -> Result<Self, Self::Error> {
+        let day = parse_day(&item.day)?;
+
+        Ok(Self {
             day,
-            open: item.open.parse::<f64>().unwrap(),
-            high: item.high.parse::<f64>().unwrap(),
-            low: item.low.parse::<f64>().unwrap(),
-            close: item.close.parse::<f64>().unwrap(),
-            volume: item.volume.parse::<f64>().unwrap(),
-            amount: item
-                .amount
-                .map(|x| x.parse::<f64>().unwrap())
-                .unwrap_or(0.0),
+            open: parse_field("open", &item.open)?,
+            high: parse_field("high", &item.high)?,
+            low: parse_field("low", &item.low)?,
+            close: parse_field("close", &item.close)?,
+            volume: parse_field("volume", &item.volume)?,
+            amount: match item.amount {
+                Some(x) => parse_field("amount", &x)?,
+                None => 0.0,
+            },
+        })
+    }
+}
+
+/// Why a quote line or kline row could not be decoded.
+#[derive(Debug, Clone, PartialEq)]
+pub enum QuoteParseError {
+    /// The line does not have the fields we expect for its format.
+    Layout { raw: String },
+    /// A single field held something other than what it should.
+    Field { field: &'static str, raw: String },
+}
+
+impl Display for QuoteParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            QuoteParseError::Layout { raw } => write!(f, "unexpected layout: {:?}", raw),
+            QuoteParseError::Field { field, raw } => write!(f, "bad {}: {:?}", field, raw),
         }
     }
 }
 
+impl std::error::Error for QuoteParseError {}
+
+pub(crate) fn parse_field<T: FromStr>(
+    field: &'static str,
+    raw: &str,
+) -> Result<T, QuoteParseError> {
+    raw.trim().parse::<T>().map_err(|_| QuoteParseError::Field {
+        field,
+        raw: raw.to_string(),
+    })
+}
+
+/// Accepts both `2024-09-25 10:45:00` and `2024-09-25`.
+pub(crate) fn parse_day(raw: &str) -> Result<NaiveDateTime, QuoteParseError> {
+    NaiveDateTime::parse_from_str(raw, "%Y-%m-%d %H:%M:%S")
+        .or_else(|_| NaiveDate::parse_from_str(raw, "%Y-%m-%d").map(NaiveDateTime::from))
+        .map_err(|_| QuoteParseError::Field {
+            field: "day",
+            raw: raw.to_string(),
+        })
+}
+
 pub fn check_stock_code(code: &str) -> bool {
-    REG.is_match(code)
+    SecurityId::parse(code).is_ok()
 }
 
 impl Stock {
@@ -136,12 +242,65 @@ impl Stock {
         }
     }
 
+    /// Keep the name in sync with the latest decoded quote, so a fix on the
+    /// decoding side also repairs stocks that are already on screen.
+    pub fn set_name(&mut self, name: &str) {
+        if !name.is_empty() && self.name != name {
+            self.name = name.to_string();
+        }
+    }
+
     pub fn set_data(&mut self, data: BaseData) {
         self.data = data;
     }
 
+    /// Replace the bars covered by the latest fetch, keeping any older
+    /// history loaded by scrolling back.
     pub fn set_klines(&mut self, klines: Vec<KlineItem>) {
-        self.klines = klines;
+        let Some(first) = klines.first() else {
+            return;
+        };
+        let keep = self.klines.partition_point(|x| x.day < first.day);
+        self.klines.truncate(keep);
+        self.klines.extend(klines);
+    }
+
+    /// Fold in a locally built bar. The fetched bar for the same period
+    /// may have seen trades from before we started polling, so volume and
+    /// range only ever grow; a bar older than the last one is ignored.
+    pub fn merge_kline(&mut self, bar: KlineItem) {
+        let Some(last) = self.klines.last_mut() else {
+            return;
+        };
+        if last.day == bar.day {
+            last.high = last.high.max(bar.high);
+            last.low = last.low.min(bar.low);
+            last.close = bar.close;
+            last.volume = last.volume.max(bar.volume);
+            last.amount = last.amount.max(bar.amount);
+        } else if last.day < bar.day {
+            self.klines.push(bar);
+        }
+    }
+
+    /// Put bars older than the loaded ones in front; returns how many.
+    pub fn prepend_klines(&mut self, older: Vec<KlineItem>) -> usize {
+        let mut older = match self.klines.first() {
+            Some(first) => older.into_iter().filter(|x| x.day < first.day).collect(),
+            None => older,
+        };
+        let added = older.len();
+        older.append(&mut self.klines);
+        self.klines = older;
+        added
+    }
+
+    /// Drop the loaded bars, e.g. when switching scale.
+    pub fn clear_klines(&mut self) {
+        self.klines.clear();
+        self.history_loading = false;
+        self.history_exhausted = false;
+        self.view_shift = 0;
     }
 
     #[inline]
@@ -184,6 +343,14 @@ impl Stock {
         self.data.amount
     }
 
+    #[inline]
+    pub fn data_open_interest(&self) -> Option<f64> {
+        match &self.data.instrument {
+            Instrument::Futures(f) => Some(f.open_interest),
+            Instrument::Equity => None,
+        }
+    }
+
     #[inline]
     pub fn data_bids(&self) -> &Vec<(Vol, Price)> {
         &self.data.bids
@@ -193,98 +360,4 @@ impl Stock {
     pub fn data_asks(&self) -> &Vec<(Vol, Price)> {
         &self.data.asks
     }
-
-    pub fn get_kelines(
-        code: &str,
-        scale: &usize,
-        datalen: u32,
-    ) -> Result<Vec<KlineItem>, reqwest::Error> {
-        let l =   reqwest::blocking::get(format!("https://quotes.sina.cn/cn/api/json_v2.php/CN_MarketDataService.getKLineData?symbol={code}&scale={scale}&ma=no&datalen={datalen}"))
-        ?.json::<Vec<KlineItemD>>()?;
-        let d = l.into_iter().map(KlineItem::from).collect();
-        Ok(d)
-    }
-}
-
-pub fn fetch_blocking(
-    codes: Vec<String>,
-) -> Result<Vec<(String, String, BaseData)>, reqwest::Error> {
-    let code_string = codes.join(",");
-    let url = format!("{}/list={}", BASE_URL, code_string);
-
-    let str = reqwest::blocking::Client::new()
-        .get(&url)
-        .header("Referer", "https://www.sina.com.cn/")
-        .send()?
-        .text()?;
-
-    let stocks = str
-        .trim()
-        .split('\n')
-        .filter(|x| x.len() > MIN_LEN)
-        .filter_map(|stock_item_str| decode_from_string(stock_item_str))
-        .collect();
-    Ok(stocks)
-}
-
-fn decode_from_string(stock_string: &str) -> Option<(String, String, BaseData)> {
-    let mut list: Vec<&str> = stock_string.trim().split(",").collect();
-    list.truncate(32);
-    // name
-
-    match list.as_slice() {
-        [code_and_name, opening_str, closing_str, new_str, high, low, bid, ask, vol, amount, rest @ .., date, time] =>
-        {
-            let name_str: Vec<&str> = code_and_name.split("=\"").collect();
-            let code = name_str[0].replace("var hq_str_", "");
-            let name = name_str[1];
-            let opening = opening_str.parse::<Price>().unwrap();
-            let closing = closing_str.parse::<Price>().unwrap();
-            let new = new_str.parse::<Price>().unwrap();
-            let percent = ((new - closing) / closing * 10000.0).round() / 100.0;
-
-            let bids = rest[0..10]
-                .chunks(2)
-                .into_iter()
-                .map(|x| {
-                    if let [v, p] = x {
-                        (v.parse::<Vol>().unwrap() / 100, p.parse::<Price>().unwrap())
-                    } else {
-                        (0, 0.0)
-                    }
-                })
-                .collect();
-
-            let asks = rest[10..20]
-                .chunks(2)
-                .into_iter()
-                .map(|x| {
-                    if let [v, p] = x {
-                        (v.parse::<Vol>().unwrap() / 100, p.parse::<Price>().unwrap())
-                    } else {
-                        (0, 0.0)
-                    }
-                })
-                .collect();
-
-            let data = BaseData {
-                opening,
-                closing,
-                new,
-                hight: high.parse::<Price>().unwrap(),
-                low: low.parse::<Price>().unwrap(),
-                bid: bid.parse::<Price>().unwrap(),
-                ask: ask.parse::<Price>().unwrap(),
-                vol: vol.parse::<Vol>().unwrap(),
-                amount: amount.parse::<f32>().unwrap(),
-                date: date.to_string(),
-                time: time.to_string(),
-                rise_per: percent,
-                bids,
-                asks,
-            };
-            Some((code.into(), name.into(), data))
-        }
-        _ => None,
-    }
 }
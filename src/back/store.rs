@@ -0,0 +1,287 @@
+//! On-disk K-line history, one CSV file per code and scale.
+//!
+//! Bars are kept in time order as `day,open,high,low,close,volume,amount`.
+//! Fetched bars overwrite stored ones from their first day on, so the
+//! still-forming bar is corrected by the next fetch. Pages of older history
+//! only ever add bars before the stored ones.
+
+use std::{
+    fs,
+    io::{self, Write},
+    path::PathBuf,
+    sync::{Arc, Mutex},
+};
+
+use chrono::NaiveDateTime;
+use tracing::debug;
+
+use super::stock::{KLineScale, KlineItem};
+
+const DAY_FORMAT: &str = "%Y-%m-%d %H:%M:%S";
+
+#[derive(Clone, Debug)]
+pub struct KlineStore {
+    dir: PathBuf,
+    /// Refresh threads may write the same file at once.
+    lock: Arc<Mutex<()>>,
+}
+
+impl KlineStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            lock: Arc::default(),
+        }
+    }
+
+    /// Intraday minutes only make sense for the running session.
+    pub fn keeps(scale: &KLineScale) -> bool {
+        *scale != KLineScale::Intraday
+    }
+
+    fn path(&self, code: &str, scale: &KLineScale) -> PathBuf {
+        self.dir.join(format!("{}_{}.csv", code, scale.label()))
+    }
+
+    /// Stored bars, oldest first; nothing if the file is missing. Rows that
+    /// do not parse are skipped.
+    pub fn load(&self, code: &str, scale: &KLineScale) -> Vec<KlineItem> {
+        let _guard = self.lock.lock();
+        self.read(code, scale)
+    }
+
+    /// Fold `fetched` into the stored bars and return the result.
+    pub fn merge(
+        &self,
+        code: &str,
+        scale: &KLineScale,
+        fetched: &[KlineItem],
+    ) -> io::Result<Vec<KlineItem>> {
+        let _guard = self.lock.lock();
+        let mut bars = self.read(code, scale);
+        if let Some(first) = fetched.first() {
+            let keep = bars.partition_point(|x| x.day < first.day);
+            bars.truncate(keep);
+            bars.extend_from_slice(fetched);
+            self.write(code, scale, &bars)?;
+        }
+        Ok(bars)
+    }
+
+    /// Add the bars of `older` that come before the first stored one,
+    /// leaving the stored ones as they are, and return the result.
+    pub fn prepend(
+        &self,
+        code: &str,
+        scale: &KLineScale,
+        older: &[KlineItem],
+    ) -> io::Result<Vec<KlineItem>> {
+        let _guard = self.lock.lock();
+        let mut bars = self.read(code, scale);
+        let keep = match bars.first() {
+            Some(first) => older.partition_point(|x| x.day < first.day),
+            None => older.len(),
+        };
+        if keep > 0 {
+            bars.splice(0..0, older[..keep].iter().cloned());
+            self.write(code, scale, &bars)?;
+        }
+        Ok(bars)
+    }
+
+    fn read(&self, code: &str, scale: &KLineScale) -> Vec<KlineItem> {
+        let Ok(text) = fs::read_to_string(self.path(code, scale)) else {
+            return vec![];
+        };
+        text.lines()
+            .filter_map(|line| {
+                let row = decode(line);
+                if row.is_none() {
+                    debug!("skipped stored kline row: {}", line);
+                }
+                row
+            })
+            .collect()
+    }
+
+    fn write(&self, code: &str, scale: &KLineScale, bars: &[KlineItem]) -> io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.path(code, scale);
+        // write aside and rename so a crash never leaves half a file
+        let tmp = path.with_extension("csv.tmp");
+        let mut out = io::BufWriter::new(fs::File::create(&tmp)?);
+        for bar in bars {
+            writeln!(out, "{}", encode(bar))?;
+        }
+        out.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        fs::rename(tmp, path)
+    }
+}
+
+fn encode(bar: &KlineItem) -> String {
+    format!(
+        "{},{},{},{},{},{},{}",
+        bar.day.format(DAY_FORMAT),
+        bar.open,
+        bar.high,
+        bar.low,
+        bar.close,
+        bar.volume,
+        bar.amount
+    )
+}
+
+fn decode(line: &str) -> Option<KlineItem> {
+    let fields = line.split(',').collect::<Vec<&str>>();
+    let [day, open, high, low, close, volume, amount] = fields[..] else {
+        return None;
+    };
+    Some(KlineItem {
+        day: NaiveDateTime::parse_from_str(day, DAY_FORMAT).ok()?,
+        open: open.parse().ok()?,
+        high: high.parse().ok()?,
+        low: low.parse().ok()?,
+        close: close.parse().ok()?,
+        volume: volume.parse().ok()?,
+        amount: amount.parse().ok()?,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use std::process;
+
+    use chrono::{NaiveDate, TimeDelta};
+
+    use super::*;
+
+    /// A store in a fresh directory under the system temp dir.
+    fn store(name: &str) -> KlineStore {
+        let dir = std::env::temp_dir().join(format!("kline-store-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        KlineStore::new(dir)
+    }
+
+    /// Daily bars from 2024-09-01 on, `first` days in, closing at the day
+    /// number plus `close`.
+    fn bars(first: i64, n: i64, close: f64) -> Vec<KlineItem> {
+        let start = NaiveDate::from_ymd_opt(2024, 9, 1)
+            .unwrap()
+            .and_hms_opt(0, 0, 0)
+            .unwrap();
+        (first..first + n)
+            .map(|i| KlineItem {
+                day: start + TimeDelta::days(i),
+                close: i as f64 + close,
+                ..KlineItem::default()
+            })
+            .collect()
+    }
+
+    fn closes(bars: &[KlineItem]) -> Vec<f64> {
+        bars.iter().map(|x| x.close).collect()
+    }
+
+    #[test]
+    fn prepend_keeps_newer_bars() {
+        let store = store("prepend");
+        store
+            .merge("sh600519", &KLineScale::Day, &bars(10, 5, 0.0))
+            .unwrap();
+
+        // an older page overlapping the first stored day
+        let all = store
+            .prepend("sh600519", &KLineScale::Day, &bars(5, 6, 0.5))
+            .unwrap();
+        let expected = [5.5, 6.5, 7.5, 8.5, 9.5, 10.0, 11.0, 12.0, 13.0, 14.0];
+        assert_eq!(closes(&all), expected);
+        assert_eq!(closes(&store.load("sh600519", &KLineScale::Day)), expected);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn round_trips_every_field() {
+        let store = store("round-trip");
+        let bar = KlineItem {
+            day: NaiveDate::from_ymd_opt(2024, 9, 25)
+                .unwrap()
+                .and_hms_opt(10, 35, 0)
+                .unwrap(),
+            open: 1505.5,
+            high: 1530.25,
+            low: 1498.0,
+            close: 1520.125,
+            volume: 2_500_000.0,
+            amount: 3_780_000_000.5,
+        };
+        store
+            .merge("sh600519", &KLineScale::Munute5, &[bar.clone()])
+            .unwrap();
+
+        let loaded = store.load("sh600519", &KLineScale::Munute5);
+        assert_eq!(loaded.len(), 1);
+        assert_eq!(encode(&loaded[0]), encode(&bar));
+        assert_eq!(loaded[0].day, bar.day);
+        assert_eq!(loaded[0].amount, bar.amount);
+        // other scales and codes live in their own files
+        assert!(store.load("sh600519", &KLineScale::Day).is_empty());
+        assert!(store.load("sz000001", &KLineScale::Munute5).is_empty());
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn merge_replaces_from_the_first_fetched_day() {
+        let store = store("merge");
+        store
+            .merge("sh600519", &KLineScale::Day, &bars(0, 5, 0.0))
+            .unwrap();
+        // the forming bar of day 4 is corrected and day 5 added
+        let all = store
+            .merge("sh600519", &KLineScale::Day, &bars(3, 3, 0.5))
+            .unwrap();
+        let expected = [0.0, 1.0, 2.0, 3.5, 4.5, 5.5];
+        assert_eq!(closes(&all), expected);
+        assert_eq!(closes(&store.load("sh600519", &KLineScale::Day)), expected);
+        // nothing fetched leaves the file alone
+        assert_eq!(
+            closes(&store.merge("sh600519", &KLineScale::Day, &[]).unwrap()),
+            expected
+        );
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn writes_through_a_renamed_temp_file() {
+        let store = store("rename");
+        store
+            .merge("sh600519", &KLineScale::Day, &bars(0, 3, 0.0))
+            .unwrap();
+        store
+            .merge("sh600519", &KLineScale::Day, &bars(2, 2, 0.0))
+            .unwrap();
+        let mut names = fs::read_dir(&store.dir)
+            .unwrap()
+            .map(|e| e.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, ["sh600519_day.csv"]);
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+
+    #[test]
+    fn skips_rows_that_do_not_parse() {
+        let store = store("corrupt");
+        store
+            .merge("sh600519", &KLineScale::Day, &bars(0, 2, 0.0))
+            .unwrap();
+        let path = store.path("sh600519", &KLineScale::Day);
+        let mut text = fs::read_to_string(&path).unwrap();
+        text.push_str("2024-09-03 00:00:00,1,2\nnot a row\n");
+        fs::write(&path, text).unwrap();
+        assert_eq!(
+            closes(&store.load("sh600519", &KLineScale::Day)),
+            [0.0, 1.0]
+        );
+        let _ = fs::remove_dir_all(&store.dir);
+    }
+}
@@ -0,0 +1,362 @@
+//! Watchlists in and out as plain text, CSV and the files of common
+//! Chinese terminals.
+//!
+//! Tonghuashun and EastMoney export delimited tables with `代码`/`名称`
+//! headers in GBK; TDX block files (`.blk`) hold one `<market><code>` per
+//! line, market 0 for Shenzhen, 1 for Shanghai and 2 for Beijing.
+
+use encoding_rs::GBK;
+use serde::{Deserialize, Serialize};
+
+use super::security::{Exchange, SecurityId, SecurityParseError};
+
+/// Header names of the code and name columns.
+const CODE_HEADERS: [&str; 4] = ["code", "代码", "股票代码", "证券代码"];
+const NAME_HEADERS: [&str; 4] = ["name", "名称", "股票名称", "证券名称"];
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WatchlistFormat {
+    /// Codes separated by whitespace, commas or semicolons.
+    #[default]
+    Text,
+    /// `code,name` with a header row.
+    Csv,
+    Tonghuashun,
+    EastMoney,
+    TdxBlock,
+}
+
+impl WatchlistFormat {
+    pub const ALL: [WatchlistFormat; 5] = [
+        WatchlistFormat::Text,
+        WatchlistFormat::Csv,
+        WatchlistFormat::Tonghuashun,
+        WatchlistFormat::EastMoney,
+        WatchlistFormat::TdxBlock,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            WatchlistFormat::Text => "text",
+            WatchlistFormat::Csv => "CSV",
+            WatchlistFormat::Tonghuashun => "Tonghuashun",
+            WatchlistFormat::EastMoney => "EastMoney",
+            WatchlistFormat::TdxBlock => "TDX .blk",
+        }
+    }
+
+    pub fn extension(&self) -> &'static str {
+        match self {
+            WatchlistFormat::Text | WatchlistFormat::Tonghuashun => "txt",
+            WatchlistFormat::Csv | WatchlistFormat::EastMoney => "csv",
+            WatchlistFormat::TdxBlock => "blk",
+        }
+    }
+}
+
+/// One code read from a watchlist file, valid or not.
+#[derive(Clone, Debug)]
+pub struct Entry {
+    pub raw: String,
+    /// Empty when the file has no names.
+    pub name: String,
+    pub id: Result<SecurityId, SecurityParseError>,
+}
+
+impl Entry {
+    fn new(raw: &str, name: &str) -> Self {
+        Self {
+            raw: raw.trim().to_string(),
+            name: name.trim().to_string(),
+            id: normalise(raw),
+        }
+    }
+}
+
+/// Files from Chinese terminals are GBK unless they are valid UTF-8.
+pub fn decode(bytes: &[u8]) -> String {
+    match std::str::from_utf8(bytes) {
+        Ok(text) => text.trim_start_matches('\u{feff}').to_string(),
+        Err(_) => GBK.decode(bytes).0.into_owned(),
+    }
+}
+
+/// Bring terminal spellings to what the code parser reads: spreadsheet
+/// quoting like `="600000"`, suffixes like `600000.SH` and TDX's
+/// `1600000`.
+pub fn normalise(raw: &str) -> Result<SecurityId, SecurityParseError> {
+    let code = raw.trim().trim_start_matches('=').trim_matches('"').trim();
+    if let Some((digits, market)) = code.split_once('.') {
+        if let Some(prefix) = ["sh", "sz", "bj"]
+            .into_iter()
+            .find(|p| market.eq_ignore_ascii_case(p))
+        {
+            return SecurityId::parse(&format!("{}{}", prefix, digits));
+        }
+    }
+    if code.len() == 7 && code.bytes().all(|b| b.is_ascii_digit()) {
+        let prefix = match &code[..1] {
+            "0" => "sz",
+            "1" => "sh",
+            "2" => "bj",
+            _ => return Err(SecurityParseError::Format(code.to_string())),
+        };
+        return SecurityId::parse(&format!("{}{}", prefix, &code[1..]));
+    }
+    SecurityId::parse(code)
+}
+
+/// Read the entries of a watchlist file in file order.
+pub fn parse(format: WatchlistFormat, text: &str) -> Vec<Entry> {
+    match format {
+        WatchlistFormat::Text => text
+            .split(|c: char| c.is_whitespace() || c == ',' || c == ';')
+            .filter(|x| !x.is_empty())
+            .map(|x| Entry::new(x, ""))
+            .collect(),
+        WatchlistFormat::TdxBlock => text
+            .lines()
+            .filter(|x| !x.trim().is_empty())
+            .map(|x| Entry::new(x, ""))
+            .collect(),
+        WatchlistFormat::Csv | WatchlistFormat::Tonghuashun | WatchlistFormat::EastMoney => {
+            parse_table(text)
+        }
+    }
+}
+
+/// A delimited table, tab separated if its first line has a tab. The code
+/// and name columns are found by header, else the first two are taken; a
+/// first row naming neither is read as data.
+fn parse_table(text: &str) -> Vec<Entry> {
+    let first = text.lines().next().unwrap_or_default();
+    let delimiter = if first.contains('\t') { b'\t' } else { b',' };
+    let mut rows = csv::ReaderBuilder::new()
+        .delimiter(delimiter)
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(text.as_bytes())
+        .into_records()
+        .filter_map(Result::ok)
+        .peekable();
+    let find = |header: &csv::StringRecord, names: &[&str]| {
+        header
+            .iter()
+            .position(|h| names.iter().any(|n| h.trim().eq_ignore_ascii_case(n)))
+    };
+    let header = rows
+        .peek()
+        .map(|first| (find(first, &CODE_HEADERS), find(first, &NAME_HEADERS)));
+    let (code, name) = match header {
+        Some((code, name)) if code.is_some() || name.is_some() => {
+            rows.next();
+            (code.unwrap_or(0), name)
+        }
+        _ => (0, Some(1)),
+    };
+    rows.filter_map(|row| {
+        let raw = row.get(code)?;
+        (!raw.trim().is_empty()).then(|| {
+            let name = name.and_then(|i| row.get(i)).unwrap_or_default();
+            Entry::new(raw, name)
+        })
+    })
+    .collect()
+}
+
+/// The bare six digits when they read back as the same instrument,
+/// otherwise the full symbol.
+fn short_code(id: &SecurityId) -> String {
+    match SecurityId::parse(&id.code) {
+        Ok(bare) if bare == *id => id.code.clone(),
+        _ => id.symbol(),
+    }
+}
+
+/// Write `(id, name)` pairs in `format`. TDX blocks only hold A-shares, so
+/// the other entries are left out; returns the bytes and how many were.
+pub fn render(format: WatchlistFormat, items: &[(SecurityId, String)]) -> (Vec<u8>, usize) {
+    let mut skipped = 0;
+    let text = match format {
+        WatchlistFormat::Text => items
+            .iter()
+            .map(|(id, _)| id.symbol() + "\n")
+            .collect::<String>(),
+        WatchlistFormat::Csv => {
+            let mut out = csv::Writer::from_writer(vec![]);
+            out.write_record(["code", "name"]).ok();
+            for (id, name) in items {
+                out.write_record([id.symbol().as_str(), name]).ok();
+            }
+            return (out.into_inner().unwrap_or_default(), 0);
+        }
+        WatchlistFormat::Tonghuashun => {
+            let rows = items.iter().map(|(id, name)| {
+                let code = match id.is_a_share() {
+                    true => id.symbol().to_ascii_uppercase(),
+                    false => id.symbol(),
+                };
+                format!("{}\t{}\r\n", code, name)
+            });
+            std::iter::once("代码\t名称\r\n".to_string())
+                .chain(rows)
+                .collect::<String>()
+        }
+        WatchlistFormat::EastMoney => {
+            let rows = items
+                .iter()
+                .map(|(id, name)| format!("{},{}\r\n", short_code(id), name));
+            std::iter::once("代码,名称\r\n".to_string())
+                .chain(rows)
+                .collect::<String>()
+        }
+        WatchlistFormat::TdxBlock => items
+            .iter()
+            .filter_map(|(id, _)| {
+                let market = match id.exchange {
+                    Exchange::Sz => "0",
+                    Exchange::Sh => "1",
+                    Exchange::Bj => "2",
+                    _ => {
+                        skipped += 1;
+                        return None;
+                    }
+                };
+                Some(format!("{}{}\r\n", market, id.code))
+            })
+            .collect::<String>(),
+    };
+    let bytes = match format {
+        WatchlistFormat::Tonghuashun | WatchlistFormat::EastMoney => {
+            GBK.encode(&text).0.into_owned()
+        }
+        _ => text.into_bytes(),
+    };
+    (bytes, skipped)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(raw: &str) -> SecurityId {
+        SecurityId::parse(raw).unwrap()
+    }
+
+    fn symbols(entries: &[Entry]) -> Vec<String> {
+        entries
+            .iter()
+            .map(|e| match &e.id {
+                Ok(id) => id.symbol(),
+                Err(_) => format!("!{}", e.raw),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn normalises_terminal_spellings() {
+        let cases = [
+            ("=\"600000\"", "sh600000"),
+            (" 600000.SH ", "sh600000"),
+            ("000001.sz", "sz000001"),
+            ("430047.BJ", "bj430047"),
+            ("1600000", "sh600000"),
+            ("0000001", "sz000001"),
+            ("2430047", "bj430047"),
+            ("hk00700", "hk00700"),
+        ];
+        for (raw, symbol) in cases {
+            assert_eq!(
+                normalise(raw).map(|x| x.symbol()),
+                Ok(symbol.to_string()),
+                "{}",
+                raw
+            );
+        }
+        assert!(normalise("9600000").is_err());
+        assert!(normalise("600000.XX").is_err());
+    }
+
+    #[test]
+    fn decodes_gbk_and_utf8() {
+        let (gbk, _, _) = GBK.encode("代码\t名称");
+        assert_eq!(decode(&gbk), "代码\t名称");
+        assert_eq!(decode("\u{feff}代码,名称".as_bytes()), "代码,名称");
+    }
+
+    #[test]
+    fn detects_known_headers_only() {
+        let entries = parse(WatchlistFormat::Csv, "名称,代码\n浦发银行,600000\n");
+        assert_eq!(symbols(&entries), ["sh600000"]);
+        assert_eq!(entries[0].name, "浦发银行");
+
+        let entries = parse(
+            WatchlistFormat::Tonghuashun,
+            "证券代码\t证券名称\nSH600000\t浦发银行\n",
+        );
+        assert_eq!(symbols(&entries), ["sh600000"]);
+
+        // no header at all
+        let entries = parse(WatchlistFormat::Csv, "600000,浦发银行\n000001,平安银行\n");
+        assert_eq!(symbols(&entries), ["sh600000", "sz000001"]);
+        assert_eq!(entries[1].name, "平安银行");
+
+        // an unknown first row is kept and shows up as invalid
+        let entries = parse(WatchlistFormat::Csv, "foo,bar\n600000,浦发银行\n");
+        assert_eq!(symbols(&entries), ["!foo", "sh600000"]);
+    }
+
+    #[test]
+    fn reads_text_and_tdx_blocks() {
+        let entries = parse(WatchlistFormat::Text, "sh600000, 000001;\n hk00700\n");
+        assert_eq!(symbols(&entries), ["sh600000", "sz000001", "hk00700"]);
+
+        let entries = parse(
+            WatchlistFormat::TdxBlock,
+            "1600000\r\n\r\n0000001\r\n2430047\r\n",
+        );
+        assert_eq!(symbols(&entries), ["sh600000", "sz000001", "bj430047"]);
+    }
+
+    #[test]
+    fn render_parse_round_trips() {
+        let items = [
+            (id("sh600000"), "浦发银行".to_string()),
+            (id("sz000001"), "平安银行".to_string()),
+            (id("sh000001"), "上证指数".to_string()),
+            (id("bj430047"), "诺思兰德".to_string()),
+            (id("hk00700"), "腾讯控股".to_string()),
+        ];
+        let all = items
+            .iter()
+            .map(|(id, _)| id.symbol())
+            .collect::<Vec<String>>();
+        for format in WatchlistFormat::ALL {
+            let (bytes, skipped) = render(format, &items);
+            let entries = parse(format, &decode(&bytes));
+            let names = entries
+                .iter()
+                .map(|e| e.name.as_str())
+                .collect::<Vec<&str>>();
+            match format {
+                // Hong Kong has no TDX market number
+                WatchlistFormat::TdxBlock => {
+                    assert_eq!(skipped, 1);
+                    assert_eq!(symbols(&entries), all[..4]);
+                }
+                WatchlistFormat::Text => {
+                    assert_eq!(symbols(&entries), all);
+                    assert!(names.iter().all(|x| x.is_empty()));
+                }
+                _ => {
+                    assert_eq!(skipped, 0, "{:?}", format);
+                    assert_eq!(symbols(&entries), all, "{:?}", format);
+                    assert_eq!(
+                        names,
+                        ["浦发银行", "平安银行", "上证指数", "诺思兰德", "腾讯控股"]
+                    );
+                }
+            }
+        }
+    }
+}
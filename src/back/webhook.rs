@@ -0,0 +1,302 @@
+use std::{
+    fmt::{self, Display},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use tracing::{debug, error};
+
+use super::alert::Alert;
+
+/// Where and how alerts are POSTed.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WebhookConfig {
+    pub enabled: bool,
+    pub url: String,
+    /// Request body with `{{placeholders}}`, empty for the default payload.
+    ///
+    /// `{{code}}`, `{{name}}`, `{{rule}}` and `{{time}}` are JSON escaped
+    /// without quotes so they can sit inside string literals; `{{value}}`,
+    /// `{{price}}`, `{{rise_per}}`, `{{data}}` and `{{payload}}` are JSON
+    /// values.
+    pub template: String,
+    /// Attempts after the first one.
+    pub retries: u32,
+    /// Wait before the first retry, doubled for each further one.
+    pub backoff_ms: u64,
+}
+
+impl Default for WebhookConfig {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            url: String::new(),
+            template: String::new(),
+            retries: 3,
+            backoff_ms: 500,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum WebhookError {
+    Http(reqwest::Error),
+    /// The endpoint answered with a non-success status.
+    Status(u16),
+}
+
+impl Display for WebhookError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            WebhookError::Http(e) => write!(f, "webhook request failed: {}", e),
+            WebhookError::Status(code) => write!(f, "webhook answered {}", code),
+        }
+    }
+}
+
+impl std::error::Error for WebhookError {}
+
+impl From<reqwest::Error> for WebhookError {
+    fn from(value: reqwest::Error) -> Self {
+        WebhookError::Http(value)
+    }
+}
+
+impl WebhookError {
+    /// Network errors, throttling and server errors are worth another try.
+    fn is_transient(&self) -> bool {
+        match self {
+            WebhookError::Http(_) => true,
+            WebhookError::Status(code) => *code == 429 || *code >= 500,
+        }
+    }
+}
+
+/// The default JSON body for an alert.
+pub fn payload(alert: &Alert) -> Value {
+    json!({
+        "code": alert.code,
+        "name": alert.name,
+        "rule_id": alert.rule_id,
+        "rule": alert.condition.to_string(),
+        "value": alert.value,
+        "timestamp": alert.at.to_rfc3339(),
+        "data": alert.data,
+    })
+}
+
+/// Fill the placeholders of `template` from `alert` in one pass, so text
+/// pulled in from the alert is never expanded again. Unknown placeholders
+/// are left as they are.
+pub fn render(template: &str, alert: &Alert) -> String {
+    // a JSON string without its surrounding quotes
+    let escaped = |s: &str| {
+        let quoted = Value::from(s).to_string();
+        quoted[1..quoted.len() - 1].to_string()
+    };
+    let value = |key: &str| match key {
+        "code" => Some(escaped(&alert.code)),
+        "name" => Some(escaped(&alert.name)),
+        "rule" => Some(escaped(&alert.condition.to_string())),
+        "time" => Some(escaped(&alert.at.to_rfc3339())),
+        "value" => Some(Value::from(alert.value).to_string()),
+        "price" => Some(Value::from(alert.data.new).to_string()),
+        "rise_per" => Some(Value::from(alert.data.rise_per).to_string()),
+        "data" => Some(serde_json::to_string(&alert.data).unwrap_or_default()),
+        "payload" => Some(payload(alert).to_string()),
+        _ => None,
+    };
+    let mut body = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        body.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        match after
+            .find("}}")
+            .and_then(|end| Some((end, value(&after[..end])?)))
+        {
+            Some((end, v)) => {
+                body.push_str(&v);
+                rest = &after[end + 2..];
+            }
+            None => {
+                body.push_str("{{");
+                rest = after;
+            }
+        }
+    }
+    body.push_str(rest);
+    body
+}
+
+/// Outgoing webhook sink for alerts.
+#[derive(Clone, Debug, Default)]
+pub struct Webhook {
+    client: reqwest::blocking::Client,
+    config: WebhookConfig,
+}
+
+impl Webhook {
+    pub fn new(config: WebhookConfig) -> Self {
+        Self {
+            client: reqwest::blocking::Client::builder()
+                .timeout(Duration::from_secs(10))
+                .build()
+                .unwrap_or_default(),
+            config,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.config.enabled && !self.config.url.trim().is_empty()
+    }
+
+    pub fn body(&self, alert: &Alert) -> String {
+        match self.config.template.trim() {
+            "" => payload(alert).to_string(),
+            template => render(template, alert),
+        }
+    }
+
+    /// POST `alert`, retrying transient failures with exponential backoff.
+    /// Blocks until delivered or out of attempts.
+    pub fn send(&self, alert: &Alert) -> Result<(), WebhookError> {
+        let body = self.body(alert);
+        let mut wait = Duration::from_millis(self.config.backoff_ms);
+        let mut attempt = 0;
+        loop {
+            match self.post(&body) {
+                Ok(()) => return Ok(()),
+                Err(e) if e.is_transient() && attempt < self.config.retries => {
+                    debug!("webhook attempt {} failed: {}", attempt + 1, e);
+                    std::thread::sleep(wait);
+                    wait *= 2;
+                    attempt += 1;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    fn post(&self, body: &str) -> Result<(), WebhookError> {
+        let resp = self
+            .client
+            .post(self.config.url.trim())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()?;
+        match resp.status() {
+            status if status.is_success() => Ok(()),
+            status => Err(WebhookError::Status(status.as_u16())),
+        }
+    }
+
+    /// Send on a separate thread so retries never stall the quote loop.
+    pub fn deliver(&self, alert: &Alert) {
+        if !self.is_active() || !alert.channels.webhook {
+            return;
+        }
+        let this = self.clone();
+        let alert = alert.clone();
+        std::thread::spawn(move || {
+            if let Err(e) = this.send(&alert) {
+                error!("deliver alert {} failed: {}", alert, e);
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{BufRead, BufReader, Read, Write},
+        net::TcpListener,
+        thread,
+    };
+
+    use chrono::Local;
+
+    use super::*;
+    use crate::back::{
+        alert::{AlertCondition, Channels},
+        stock::BaseData,
+    };
+
+    /// Answer one request per status in `statuses`, returning the bodies.
+    fn serve(listener: TcpListener, statuses: &'static [u16]) -> thread::JoinHandle<Vec<String>> {
+        thread::spawn(move || {
+            statuses
+                .iter()
+                .map(|status| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let mut reader = BufReader::new(stream);
+                    let mut length = 0;
+                    loop {
+                        let mut line = String::new();
+                        reader.read_line(&mut line).unwrap();
+                        if line.trim().is_empty() {
+                            break;
+                        }
+                        if let Some((key, value)) = line.split_once(':') {
+                            if key.eq_ignore_ascii_case("content-length") {
+                                length = value.trim().parse().unwrap();
+                            }
+                        }
+                    }
+                    let mut body = vec![0; length];
+                    reader.read_exact(&mut body).unwrap();
+                    write!(
+                        reader.get_mut(),
+                        "HTTP/1.1 {} X\r\nContent-Length: 0\r\nConnection: close\r\n\r\n",
+                        status
+                    )
+                    .unwrap();
+                    String::from_utf8(body).unwrap()
+                })
+                .collect()
+        })
+    }
+
+    fn alert(name: &str) -> Alert {
+        Alert {
+            rule_id: 1,
+            code: "sh600519".to_string(),
+            name: name.to_string(),
+            condition: AlertCondition::PriceAbove(1500.0),
+            value: 1520.5,
+            at: Local::now(),
+            channels: Channels::default(),
+            data: BaseData {
+                new: 1520.5,
+                ..Default::default()
+            },
+        }
+    }
+
+    #[test]
+    fn retries_and_posts_rendered_template() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        let server = serve(listener, &[500, 200]);
+
+        let webhook = Webhook::new(WebhookConfig {
+            enabled: true,
+            url,
+            template: r#"{"text":"{{name}} {{rule}} at {{price}}","value":{{value}}}"#.to_string(),
+            retries: 2,
+            backoff_ms: 10,
+        });
+        webhook.send(&alert("贵州\"茅台\"")).unwrap();
+
+        let expected = r#"{"text":"贵州\"茅台\" price ≥ 1500 at 1520.5","value":1520.5}"#;
+        assert_eq!(server.join().unwrap(), vec![expected, expected]);
+    }
+
+    #[test]
+    fn render_does_not_expand_substituted_text() {
+        let body = render("{{name}} {{code}} {{unknown}} {{", &alert("{{code}}"));
+        assert_eq!(body, "{{code}} sh600519 {{unknown}} {{");
+    }
+}
@@ -1,7 +1,7 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use eframe::{egui::ViewportBuilder, run_native, NativeOptions};
-use stock_tracker::ui::StockTrackerApp;
+use stock_tracker::ui::{StockTrackerApp, APP_NAME};
 use tracing_subscriber;
 
 fn main() {
@@ -29,7 +29,7 @@ fn main() {
     // };
 
     let _ = run_native(
-        "St Tracker",
+        APP_NAME,
         native_options,
         Box::new(|cc| Ok(Box::new(StockTrackerApp::new(cc)))),
     );
@@ -0,0 +1,309 @@
+use std::time::{Duration, Instant};
+
+use eframe::{
+    egui::{
+        self, Button, CollapsingHeader, ComboBox, DragValue, Layout, RichText, TextEdit, TextStyle,
+    },
+    emath::Align,
+    epaint::Color32,
+};
+
+use super::StockTrackerApp;
+use crate::back::{
+    alert::{Alert, AlertCondition, AlertRule, Channels},
+    message::ToBackend,
+};
+
+/// Alerts kept in the history list, oldest dropped first.
+const MAX_ALERTS: usize = 50;
+/// How long a row blinks after its alert fired.
+const FLASH: Duration = Duration::from_secs(5);
+
+#[cfg(feature = "sound")]
+const SOUND_HINT: &str = "played for rules with 🔊 on";
+#[cfg(not(feature = "sound"))]
+const SOUND_HINT: &str = "built without the `sound` feature, sounds are not played";
+
+/// 🖵 desktop notification, 🔊 sound and 🌐 webhook switches; true if one
+/// was toggled.
+fn channel_toggles(ui: &mut egui::Ui, channels: &mut Channels) -> bool {
+    let desktop = ui
+        .toggle_value(&mut channels.desktop, "🖵")
+        .on_hover_text("desktop notification");
+    let sound = ui
+        .toggle_value(&mut channels.sound, "🔊")
+        .on_hover_text("sound");
+    let webhook = ui
+        .toggle_value(&mut channels.webhook, "🌐")
+        .on_hover_text("webhook");
+    desktop.changed() || sound.changed() || webhook.changed()
+}
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    PriceAbove,
+    PriceBelow,
+    RiseAbove,
+    FallBelow,
+    VolumeAbove,
+}
+
+impl Kind {
+    const ALL: [Kind; 5] = [
+        Kind::PriceAbove,
+        Kind::PriceBelow,
+        Kind::RiseAbove,
+        Kind::FallBelow,
+        Kind::VolumeAbove,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            Kind::PriceAbove => "price ≥",
+            Kind::PriceBelow => "price ≤",
+            Kind::RiseAbove => "rise ≥ %",
+            Kind::FallBelow => "fall ≥ %",
+            Kind::VolumeAbove => "volume ≥",
+        }
+    }
+}
+
+/// The add-rule row of the alerts section.
+pub(super) struct AlertForm {
+    code: String,
+    kind: Kind,
+    threshold: f64,
+    cooldown_secs: u64,
+    hysteresis: f64,
+    channels: Channels,
+}
+
+impl Default for AlertForm {
+    fn default() -> Self {
+        Self {
+            code: String::new(),
+            kind: Kind::PriceAbove,
+            threshold: 0.0,
+            cooldown_secs: 300,
+            hysteresis: 0.0,
+            channels: Channels {
+                desktop: true,
+                ..Default::default()
+            },
+        }
+    }
+}
+
+impl AlertForm {
+    fn condition(&self) -> AlertCondition {
+        match self.kind {
+            Kind::PriceAbove => AlertCondition::PriceAbove(self.threshold),
+            Kind::PriceBelow => AlertCondition::PriceBelow(self.threshold),
+            Kind::RiseAbove => AlertCondition::RiseAbove(self.threshold),
+            Kind::FallBelow => AlertCondition::FallBelow(self.threshold),
+            Kind::VolumeAbove => AlertCondition::VolumeAbove(self.threshold.max(0.0) as u64),
+        }
+    }
+}
+
+impl StockTrackerApp {
+    pub(super) fn send_alert_rules(&self) {
+        if let Some(tx) = &self.front_tx {
+            let _ = tx.send(ToBackend::SetAlerts(self.setting.alerts.clone()));
+        }
+    }
+
+    pub(super) fn send_alert_sound(&self) {
+        if let Some(tx) = &self.front_tx {
+            let _ = tx.send(ToBackend::SetAlertSound(self.setting.alert_sound.clone()));
+        }
+    }
+
+    pub(super) fn send_webhook(&self) {
+        if let Some(tx) = &self.front_tx {
+            let _ = tx.send(ToBackend::SetWebhook(self.setting.webhook.clone()));
+        }
+    }
+
+    pub(super) fn on_alert(&mut self, alert: Alert) {
+        self.flashing.insert(alert.code.clone(), Instant::now());
+        self.alert_history.push(alert);
+        let overflow = self.alert_history.len().saturating_sub(MAX_ALERTS);
+        self.alert_history.drain(..overflow);
+    }
+
+    /// Whether the row of `code` is in the lit half of its blink.
+    pub(super) fn flash_on(&self, code: &str) -> bool {
+        self.flashing
+            .get(code)
+            .map(|t| t.elapsed())
+            .is_some_and(|e| e < FLASH && (e.as_millis() / 250) % 2 == 0)
+    }
+
+    pub(super) fn alerts_contents(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("🔔").color(Color32::GOLD));
+            CollapsingHeader::new("alerts")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let mut changed = false;
+                    let mut remove = None;
+                    for rule in self.setting.alerts.iter_mut() {
+                        let name = self
+                            .stocks
+                            .get(&rule.code)
+                            .map(|s| s.name.clone())
+                            .unwrap_or_else(|| rule.code.clone());
+                        ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                            changed |= ui.checkbox(&mut rule.enabled, "").changed();
+                            changed |= channel_toggles(ui, &mut rule.channels);
+                            ui.label(
+                                RichText::new(format!("{} {}", name, rule.condition))
+                                    .color(Color32::LIGHT_BLUE),
+                            )
+                            .on_hover_text(format!(
+                                "cooldown {}s, hysteresis {}",
+                                rule.cooldown_secs, rule.hysteresis
+                            ));
+                            ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
+                                let close_btn = ui.add(Button::new(
+                                    RichText::new("❌")
+                                        .text_style(TextStyle::Body)
+                                        .color(Color32::RED),
+                                ));
+                                if close_btn.clicked() {
+                                    remove = Some(rule.id);
+                                }
+                            });
+                        });
+                    }
+                    if let Some(id) = remove {
+                        self.setting.alerts.retain(|r| r.id != id);
+                        changed = true;
+                    }
+
+                    let mut codes = self.stocks.keys().cloned().collect::<Vec<String>>();
+                    codes.sort();
+                    let form = &mut self.alert_form;
+                    ui.horizontal_wrapped(|ui| {
+                        let selected = self
+                            .stocks
+                            .get(&form.code)
+                            .map(|s| s.name.clone())
+                            .unwrap_or_default();
+                        ComboBox::from_id_salt("alert_code")
+                            .selected_text(selected)
+                            .width(64.0)
+                            .show_ui(ui, |ui| {
+                                for code in codes {
+                                    let name = self.stocks[&code].name.clone();
+                                    ui.selectable_value(&mut form.code, code, name);
+                                }
+                            });
+                        ComboBox::from_id_salt("alert_kind")
+                            .selected_text(form.kind.label())
+                            .width(64.0)
+                            .show_ui(ui, |ui| {
+                                for kind in Kind::ALL {
+                                    ui.selectable_value(&mut form.kind, kind, kind.label());
+                                }
+                            });
+                        ui.add(DragValue::new(&mut form.threshold).speed(0.01));
+                        ui.add(
+                            DragValue::new(&mut form.cooldown_secs)
+                                .range(0..=86400)
+                                .prefix("cd ")
+                                .suffix("s"),
+                        )
+                        .on_hover_text("cooldown");
+                        ui.add(
+                            DragValue::new(&mut form.hysteresis)
+                                .range(0.0..=f64::MAX)
+                                .speed(0.01)
+                                .prefix("± "),
+                        )
+                        .on_hover_text("distance back past the threshold that re-arms the rule");
+                        channel_toggles(ui, &mut form.channels);
+                        let add_btn = ui.add_enabled(!form.code.is_empty(), Button::new("➕"));
+                        if add_btn.clicked() {
+                            let id =
+                                self.setting.alerts.iter().map(|r| r.id).max().unwrap_or(0) + 1;
+                            self.setting.alerts.push(AlertRule {
+                                id,
+                                code: form.code.clone(),
+                                condition: form.condition(),
+                                enabled: true,
+                                cooldown_secs: form.cooldown_secs,
+                                hysteresis: form.hysteresis,
+                                channels: form.channels,
+                            });
+                            changed = true;
+                        }
+                    });
+                    if changed {
+                        self.send_alert_rules();
+                    }
+
+                    ui.horizontal(|ui| {
+                        ui.label("🔊");
+                        let sound = ui
+                            .add(
+                                TextEdit::singleline(&mut self.setting.alert_sound)
+                                    .hint_text("sound file (wav, ogg, mp3)"),
+                            )
+                            .on_hover_text(SOUND_HINT);
+                        if sound.lost_focus() {
+                            self.send_alert_sound();
+                        }
+                    });
+                    self.webhook_contents(ui);
+                });
+        });
+    }
+
+    fn webhook_contents(&mut self, ui: &mut egui::Ui) {
+        let webhook = &mut self.setting.webhook;
+        let mut changed = false;
+        ui.horizontal(|ui| {
+            ui.label("🌐");
+            changed |= ui.checkbox(&mut webhook.enabled, "").changed();
+            changed |= ui
+                .add(TextEdit::singleline(&mut webhook.url).hint_text("webhook url"))
+                .lost_focus();
+        });
+        ui.horizontal(|ui| {
+            changed |= ui
+                .add(
+                    DragValue::new(&mut webhook.retries)
+                        .range(0..=10)
+                        .prefix("retries "),
+                )
+                .changed();
+            changed |= ui
+                .add(
+                    DragValue::new(&mut webhook.backoff_ms)
+                        .range(0..=60000)
+                        .speed(10.0)
+                        .prefix("backoff ")
+                        .suffix(" ms"),
+                )
+                .changed();
+        });
+        changed |= ui
+            .add(
+                TextEdit::multiline(&mut webhook.template)
+                    .hint_text(r#"{"text": "{{name}} {{rule}} at {{value}}"}"#)
+                    .desired_rows(2)
+                    .code_editor(),
+            )
+            .on_hover_text(
+                "empty for the default payload\n\
+                 {{code}} {{name}} {{rule}} {{time}}: escaped strings\n\
+                 {{value}} {{price}} {{rise_per}} {{data}} {{payload}}: JSON values",
+            )
+            .lost_focus();
+        if changed {
+            self.send_webhook();
+        }
+    }
+}
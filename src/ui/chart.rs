@@ -0,0 +1,576 @@
+use chrono::NaiveTime;
+use eframe::{
+    egui::{self, Id, Stroke},
+    epaint::Color32,
+};
+use egui_plot::{
+    AxisHints, Bar, BarChart, BoxElem, BoxPlot, BoxSpread, HLine, HPlacement, Legend, Line,
+    LineStyle, Plot, PlotBounds, PlotPoints,
+};
+use serde::{Deserialize, Serialize};
+
+use super::portfolio::format_amount;
+use crate::back::{
+    indicator::{Atr, Boll, BollValue, Kdj, KdjValue, Ma, Macd, Obv, Rsi, Series},
+    stock::{KLineScale, KlineItem},
+    DEFAULT_KLINE_BARS,
+};
+
+const MA_PERIODS: [usize; 4] = [5, 10, 20, 60];
+const MA_COLORS: [Color32; 4] = [
+    Color32::WHITE,
+    Color32::YELLOW,
+    Color32::from_rgb(255, 0, 255),
+    Color32::LIGHT_GREEN,
+];
+const RSI_PERIOD: usize = 14;
+const ATR_PERIOD: usize = 14;
+/// Height of each sub-pane below the price plot.
+const PANE_HEIGHT: f32 = 90.0;
+
+/// Bars per K-line request, persisted with the settings.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(transparent)]
+pub(super) struct KlineBars(pub u32);
+
+impl Default for KlineBars {
+    fn default() -> Self {
+        Self(DEFAULT_KLINE_BARS)
+    }
+}
+
+/// Overlays and sub-panes shown in a stock's K-line viewport.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct Studies {
+    /// One switch per `MA_PERIODS` entry.
+    pub ma: [bool; 4],
+    pub boll: bool,
+    pub volume: bool,
+    pub macd: bool,
+    pub rsi: bool,
+    pub kdj: bool,
+    pub atr: bool,
+    pub obv: bool,
+}
+
+impl Default for Studies {
+    fn default() -> Self {
+        Self {
+            ma: [false; 4],
+            boll: false,
+            volume: true,
+            macd: false,
+            rsi: false,
+            kdj: false,
+            atr: false,
+            obv: false,
+        }
+    }
+}
+
+impl Studies {
+    fn panes(&self) -> usize {
+        [
+            self.volume,
+            self.macd,
+            self.rsi,
+            self.kdj,
+            self.atr,
+            self.obv,
+        ]
+        .iter()
+        .filter(|x| **x)
+        .count()
+    }
+
+    pub fn toggles(&mut self, ui: &mut egui::Ui) {
+        for (on, period) in self.ma.iter_mut().zip(MA_PERIODS) {
+            ui.toggle_value(on, format!("MA{}", period));
+        }
+        ui.toggle_value(&mut self.boll, "BOLL");
+        ui.separator();
+        ui.toggle_value(&mut self.volume, "VOL");
+        ui.toggle_value(&mut self.macd, "MACD");
+        ui.toggle_value(&mut self.rsi, "RSI");
+        ui.toggle_value(&mut self.kdj, "KDJ");
+        ui.toggle_value(&mut self.atr, "ATR");
+        ui.toggle_value(&mut self.obv, "OBV");
+    }
+}
+
+/// Indicator values of one stock's K-lines, kept between frames and only
+/// extended as bars arrive. Studies that are switched off are not updated
+/// until they are switched on again.
+pub(super) struct Indicators {
+    scale: KLineScale,
+    ma: [Series<Ma>; 4],
+    boll: Series<Boll>,
+    macd: Series<Macd>,
+    rsi: Series<Rsi>,
+    kdj: Series<Kdj>,
+    atr: Series<Atr>,
+    obv: Series<Obv>,
+}
+
+impl Indicators {
+    pub fn new(scale: &KLineScale) -> Self {
+        Self {
+            scale: scale.clone(),
+            ma: MA_PERIODS.map(|period| Series::new(Ma::new(period))),
+            boll: Series::new(Boll::default()),
+            macd: Series::new(Macd::default()),
+            rsi: Series::new(Rsi::new(RSI_PERIOD)),
+            kdj: Series::new(Kdj::default()),
+            atr: Series::new(Atr::new(ATR_PERIOD)),
+            obv: Series::new(Obv::default()),
+        }
+    }
+
+    /// Start over, for when loaded bars were replaced or prepended.
+    pub fn reset(&mut self) {
+        *self = Self::new(&self.scale);
+    }
+
+    /// Catch up with `klines` for the enabled studies.
+    pub fn sync(&mut self, scale: &KLineScale, klines: &[KlineItem], studies: &Studies) {
+        if self.scale != *scale {
+            *self = Self::new(scale);
+        }
+        for (series, on) in self.ma.iter_mut().zip(studies.ma) {
+            if on {
+                series.sync(klines);
+            }
+        }
+        if studies.boll {
+            self.boll.sync(klines);
+        }
+        if studies.macd {
+            self.macd.sync(klines);
+        }
+        if studies.rsi {
+            self.rsi.sync(klines);
+        }
+        if studies.kdj {
+            self.kdj.sync(klines);
+        }
+        if studies.atr {
+            self.atr.sync(klines);
+        }
+        if studies.obv {
+            self.obv.sync(klines);
+        }
+    }
+}
+
+fn up_down_color(x: &KlineItem) -> Color32 {
+    if x.close < x.open {
+        Color32::GREEN
+    } else {
+        Color32::RED
+    }
+}
+
+/// Bars are plotted by index, which keeps nights, weekends and holidays
+/// out of the chart; this maps an x position back to its bar.
+fn bar_at(klines: &[KlineItem], x: f64) -> Option<(usize, &KlineItem)> {
+    let i = x.round();
+    (i >= 0.0)
+        .then_some(i as usize)
+        .and_then(|i| klines.get(i).map(|bar| (i, bar)))
+}
+
+/// Date only for daily and longer bars.
+fn day_format(klines: &[KlineItem]) -> &'static str {
+    if klines.iter().all(|x| x.day.time() == NaiveTime::MIN) {
+        "%Y-%m-%d"
+    } else {
+        "%m-%d %H:%M"
+    }
+}
+
+/// Date, OHLC, volume, amount and change from the previous close.
+fn readout(klines: &[KlineItem], x: f64) -> String {
+    let Some((i, bar)) = bar_at(klines, x) else {
+        return String::new();
+    };
+    let change = i
+        .checked_sub(1)
+        .and_then(|p| klines.get(p))
+        .filter(|prev| prev.close != 0.0)
+        .map(|prev| format!("\n{:+.2}%", (bar.close - prev.close) / prev.close * 100.0))
+        .unwrap_or_default();
+    format!(
+        "{}\nO {:.3}  H {:.3}\nL {:.3}  C {:.3}\nVOL {}  AMT {}{}",
+        bar.day.format(day_format(klines)),
+        bar.open,
+        bar.high,
+        bar.low,
+        bar.close,
+        format_amount(bar.volume),
+        format_amount(bar.amount),
+        change
+    )
+}
+
+/// `(index, value)` points, skipping bars without a value yet.
+fn points(values: impl IntoIterator<Item = Option<f64>>) -> PlotPoints<'static> {
+    values
+        .into_iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.map(|v| [i as f64, v]))
+        .collect::<Vec<[f64; 2]>>()
+        .into()
+}
+
+/// The candle plot with its overlays, and the enabled sub-panes below it,
+/// all dragged together along x.
+///
+/// `indicators` must have been synced with `klines`. `view_shift` bars
+/// were prepended since the last frame and the view is moved right by as
+/// much; returns true while the user drags past the oldest loaded bar.
+pub(super) fn kline_chart(
+    ui: &mut egui::Ui,
+    code: &str,
+    klines: &[KlineItem],
+    studies: &Studies,
+    indicators: &Indicators,
+    view_shift: &mut usize,
+) -> bool {
+    let link = Id::new(format!("{}_kline_link", code));
+    let panes = studies.panes() as f32;
+    let price_height = (ui.available_height() - panes * (PANE_HEIGHT + 4.0)).max(120.0);
+    let format = day_format(klines);
+    let sub_plot = |name: &str| {
+        Plot::new(format!("{}_{}", code, name))
+            .show_axes([false, true])
+            .label_formatter(move |name, point| {
+                let day = bar_at(klines, point.x)
+                    .map(|(_, bar)| bar.day.format(format).to_string())
+                    .unwrap_or_default();
+                format!("{}\n{} {:.3}", day, name, point.y)
+            })
+            .show_background(false)
+            .show_grid(true)
+            .allow_drag([true, false])
+            .allow_zoom([true, false])
+            .link_axis(link, [true, false])
+            .link_cursor(link, [true, false])
+            .height(PANE_HEIGHT)
+            .y_axis_min_width(48.0)
+            .legend(Legend::default())
+    };
+
+    let boxs = klines
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let fill_color = up_down_color(x);
+            BoxElem::new(
+                i as f64,
+                BoxSpread::new(x.low, x.open, (x.open + x.close) / 2.0, x.close, x.high),
+            )
+            .stroke(Stroke::new(0.2, fill_color))
+            .fill(fill_color.linear_multiply(0.05))
+            .box_width(0.8)
+        })
+        .collect();
+    let mas = MA_PERIODS
+        .iter()
+        .zip(studies.ma)
+        .zip(MA_COLORS)
+        .zip(&indicators.ma)
+        .filter(|(((_, on), _), _)| *on)
+        .map(|(((period, _), color), series)| {
+            Line::new(points(series.values().iter().copied()))
+                .color(color)
+                .name(format!("MA{}", period))
+        })
+        .collect::<Vec<Line>>();
+    let boll = studies.boll.then(|| indicators.boll.values());
+
+    let wants_older = Plot::new(format!("{}_kline", code))
+        .show_background(false)
+        .show_grid(true)
+        .allow_drag([true, false])
+        .allow_zoom([true, false])
+        .link_axis(link, [true, false])
+        .link_cursor(link, [true, false])
+        .height(price_height)
+        .y_axis_min_width(48.0)
+        .legend(Legend::default())
+        .custom_x_axes(vec![AxisHints::new_x().formatter(|mark, _range| {
+            if mark.value.fract() != 0.0 {
+                return String::new();
+            }
+            bar_at(klines, mark.value)
+                .map(|(_, bar)| bar.day.format(format).to_string())
+                .unwrap_or_default()
+        })])
+        .label_formatter(|_name, point| readout(klines, point.x))
+        .show(ui, |plot_ui| {
+            if *view_shift > 0 {
+                let bounds = plot_ui.plot_bounds();
+                let shift = *view_shift as f64;
+                plot_ui.set_plot_bounds(PlotBounds::from_min_max(
+                    [bounds.min()[0] + shift, bounds.min()[1]],
+                    [bounds.max()[0] + shift, bounds.max()[1]],
+                ));
+                *view_shift = 0;
+            }
+            plot_ui.box_plot(BoxPlot::new(boxs));
+            mas.into_iter().for_each(|line| plot_ui.line(line));
+            if let Some(boll) = boll {
+                let band =
+                    |f: fn(&BollValue) -> f64| points(boll.iter().map(|b| b.as_ref().map(f)));
+                let gray = Color32::GRAY;
+                plot_ui.line(Line::new(band(|b| b.upper)).color(gray).name("BOLL"));
+                plot_ui.line(Line::new(band(|b| b.mid)).color(gray).name("BOLL"));
+                plot_ui.line(Line::new(band(|b| b.lower)).color(gray).name("BOLL"));
+            }
+            plot_ui.response().dragged() && plot_ui.plot_bounds().min()[0] <= 0.0
+        })
+        .inner;
+
+    if studies.volume {
+        let bars = klines
+            .iter()
+            .enumerate()
+            .map(|(i, x)| {
+                Bar::new(i as f64, x.volume)
+                    .width(0.8)
+                    .fill(up_down_color(x).linear_multiply(0.5))
+            })
+            .collect();
+        sub_plot("volume").show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new(bars).name("VOL"));
+        });
+    }
+
+    if studies.macd {
+        let macd = indicators.macd.values();
+        let bars = macd
+            .iter()
+            .enumerate()
+            .map(|(i, m)| {
+                let color = if m.hist < 0.0 {
+                    Color32::GREEN
+                } else {
+                    Color32::RED
+                };
+                Bar::new(i as f64, m.hist).width(0.3).fill(color)
+            })
+            .collect();
+        sub_plot("macd").show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new(bars).name("MACD"));
+            plot_ui.line(
+                Line::new(points(macd.iter().map(|m| Some(m.dif))))
+                    .color(Color32::WHITE)
+                    .name("DIF"),
+            );
+            plot_ui.line(
+                Line::new(points(macd.iter().map(|m| Some(m.dea))))
+                    .color(Color32::YELLOW)
+                    .name("DEA"),
+            );
+        });
+    }
+
+    if studies.rsi {
+        let rsi = indicators.rsi.values();
+        sub_plot("rsi")
+            .include_y(0.0)
+            .include_y(100.0)
+            .show(ui, |plot_ui| {
+                plot_ui.line(
+                    Line::new(points(rsi.iter().copied()))
+                        .color(Color32::LIGHT_BLUE)
+                        .name(format!("RSI{}", RSI_PERIOD)),
+                );
+                let guide = Color32::GRAY.linear_multiply(0.3);
+                plot_ui.hline(HLine::new(70.0).color(guide));
+                plot_ui.hline(HLine::new(30.0).color(guide));
+            });
+    }
+
+    if studies.kdj {
+        let kdj = indicators.kdj.values();
+        sub_plot("kdj").show(ui, |plot_ui| {
+            for (name, color, f) in [
+                ("K", Color32::WHITE, (|x| x.k) as fn(&KdjValue) -> f64),
+                ("D", Color32::YELLOW, |x| x.d),
+                ("J", Color32::from_rgb(255, 0, 255), |x| x.j),
+            ] {
+                plot_ui.line(
+                    Line::new(points(kdj.iter().map(|x| Some(f(x)))))
+                        .color(color)
+                        .name(name),
+                );
+            }
+        });
+    }
+
+    if studies.atr {
+        let atr = indicators.atr.values();
+        sub_plot("atr").show(ui, |plot_ui| {
+            plot_ui.line(
+                Line::new(points(atr.iter().copied()))
+                    .color(Color32::LIGHT_BLUE)
+                    .name(format!("ATR{}", ATR_PERIOD)),
+            );
+        });
+    }
+
+    if studies.obv {
+        let obv = indicators.obv.values();
+        sub_plot("obv").show(ui, |plot_ui| {
+            plot_ui.line(
+                Line::new(points(obv.iter().map(|x| Some(*x))))
+                    .color(Color32::LIGHT_BLUE)
+                    .name("OBV"),
+            );
+        });
+    }
+    wants_older
+}
+
+/// Minutes in an A-share session, 9:30 to 11:30 and 13:00 to 15:00 with
+/// both ends included; the x axis spans at least this so the line grows
+/// across the day.
+const SESSION_MINUTES: usize = 241;
+
+/// Price and VWAP minute by minute, around the previous close, with minute
+/// volume below.
+pub(super) fn intraday_chart(
+    ui: &mut egui::Ui,
+    code: &str,
+    minutes: &[KlineItem],
+    prev_close: f64,
+) {
+    let link = Id::new(format!("{}_intraday_link", code));
+    let price_height = (ui.available_height() - (PANE_HEIGHT + 4.0)).max(120.0);
+    let span = minutes.len().max(SESSION_MINUTES) as f64;
+    let percent = move |v: f64| {
+        if prev_close > 0.0 {
+            (v - prev_close) / prev_close * 100.0
+        } else {
+            0.0
+        }
+    };
+
+    // markets without a reported amount fall back to price times volume
+    let vwap = minutes
+        .iter()
+        .scan((0.0, 0.0), |(amount, volume), x| {
+            *amount += if x.amount > 0.0 {
+                x.amount
+            } else {
+                x.close * x.volume
+            };
+            *volume += x.volume;
+            Some((*volume > 0.0).then(|| *amount / *volume))
+        })
+        .collect::<Vec<Option<f64>>>();
+    let reach = minutes
+        .iter()
+        .map(|x| x.close)
+        .chain(vwap.iter().flatten().copied())
+        .map(|v| (v - prev_close).abs())
+        .fold(0.0, f64::max);
+
+    let readout = |x: f64| {
+        let Some((i, bar)) = bar_at(minutes, x) else {
+            return String::new();
+        };
+        format!(
+            "{}\n{:.3}  {:+.2}%\nVWAP {}\nVOL {}",
+            bar.day.format("%H:%M"),
+            bar.close,
+            percent(bar.close),
+            vwap[i].map(|v| format!("{:.3}", v)).unwrap_or_default(),
+            format_amount(bar.volume)
+        )
+    };
+    let time_axis = AxisHints::new_x().formatter(|mark, _range| {
+        if mark.value.fract() != 0.0 {
+            return String::new();
+        }
+        bar_at(minutes, mark.value)
+            .map(|(_, bar)| bar.day.format("%H:%M").to_string())
+            .unwrap_or_default()
+    });
+
+    Plot::new(format!("{}_intraday", code))
+        .show_background(false)
+        .show_grid(true)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .link_axis(link, [true, false])
+        .link_cursor(link, [true, false])
+        .height(price_height)
+        .include_x(-0.5)
+        .include_x(span)
+        .include_y(prev_close - reach)
+        .include_y(prev_close + reach)
+        .custom_x_axes(vec![time_axis])
+        .custom_y_axes(vec![
+            AxisHints::new_y().min_thickness(48.0),
+            AxisHints::new_y()
+                .placement(HPlacement::Right)
+                .min_thickness(48.0)
+                .formatter(move |mark, _range| format!("{:+.2}%", percent(mark.value))),
+        ])
+        .label_formatter(|_name, point| readout(point.x))
+        .show(ui, |plot_ui| {
+            plot_ui.hline(
+                HLine::new(prev_close)
+                    .color(Color32::GRAY)
+                    .style(LineStyle::dashed_loose()),
+            );
+            plot_ui.line(
+                Line::new(points(minutes.iter().map(|x| Some(x.close))))
+                    .color(Color32::WHITE)
+                    .name("price"),
+            );
+            plot_ui.line(
+                Line::new(points(vwap.iter().copied()))
+                    .color(Color32::YELLOW)
+                    .name("VWAP"),
+            );
+        });
+
+    let bars = minutes
+        .iter()
+        .enumerate()
+        .map(|(i, x)| {
+            let prev = i
+                .checked_sub(1)
+                .and_then(|p| minutes.get(p))
+                .map_or(prev_close, |p| p.close);
+            let color = if x.close < prev {
+                Color32::GREEN
+            } else {
+                Color32::RED
+            };
+            Bar::new(i as f64, x.volume).width(0.6).fill(color)
+        })
+        .collect();
+    Plot::new(format!("{}_intraday_volume", code))
+        .show_axes([false, true])
+        .show_background(false)
+        .show_grid(true)
+        .allow_drag(false)
+        .allow_zoom(false)
+        .allow_scroll(false)
+        .link_axis(link, [true, false])
+        .link_cursor(link, [true, false])
+        .height(PANE_HEIGHT)
+        .include_x(-0.5)
+        .include_x(span)
+        .y_axis_min_width(48.0)
+        .label_formatter(|_name, point| readout(point.x))
+        .show(ui, |plot_ui| {
+            plot_ui.bar_chart(BarChart::new(bars).name("VOL"));
+        });
+}
@@ -0,0 +1,138 @@
+use std::path::PathBuf;
+
+use chrono::NaiveDate;
+use crossbeam::channel::Sender;
+use eframe::{
+    egui::{self, RichText, TextEdit},
+    epaint::Color32,
+};
+use serde::{Deserialize, Serialize};
+
+use super::import::is_imported;
+use crate::back::{
+    export::{ExportFormat, ExportRequest, ExportWhat},
+    message::ToBackend,
+    stock::KLineScale,
+};
+
+/// The export menu of the K-line viewport, remembered between sessions.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct ExportForm {
+    format: ExportFormat,
+    pub(super) dir: String,
+    // YYYY-MM-DD, empty for no bound
+    from: String,
+    to: String,
+    #[serde(skip)]
+    pub(super) last: Option<Result<(PathBuf, usize), String>>,
+}
+
+/// An empty field is no bound, anything else must be a day.
+fn parse_day(raw: &str) -> Result<Option<NaiveDate>, ()> {
+    match raw.trim() {
+        "" => Ok(None),
+        raw => NaiveDate::parse_from_str(raw, "%Y-%m-%d")
+            .map(Some)
+            .map_err(|_| ()),
+    }
+}
+
+fn day_edit(ui: &mut egui::Ui, value: &mut String, hint: &str) {
+    let color = match parse_day(value) {
+        Ok(_) => ui.visuals().text_color(),
+        Err(_) => Color32::RED,
+    };
+    ui.add(
+        TextEdit::singleline(value)
+            .hint_text(hint)
+            .text_color(color)
+            .desired_width(80.0),
+    );
+}
+
+impl ExportForm {
+    fn path(&self, stem: &str) -> PathBuf {
+        let stamp = chrono::Local::now().format("%Y%m%d-%H%M%S");
+        PathBuf::from(self.dir.trim()).join(format!(
+            "{}_{}.{}",
+            stem,
+            stamp,
+            self.format.extension()
+        ))
+    }
+
+    fn send(&mut self, tx: Option<&Sender<ToBackend>>, what: ExportWhat, stem: &str) {
+        let request = ExportRequest {
+            path: self.path(stem),
+            what,
+            format: self.format,
+        };
+        if let Some(tx) = tx {
+            let _ = tx.send(ToBackend::Export(request));
+            self.last = None;
+        }
+    }
+
+    /// Format, folder and range, then the K-lines of `code` at `scale` or
+    /// the whole watchlist. Imported instruments are unknown to the backend,
+    /// so only the watchlist is offered for them.
+    pub(super) fn menu(
+        &mut self,
+        ui: &mut egui::Ui,
+        tx: Option<&Sender<ToBackend>>,
+        code: &str,
+        scale: &KLineScale,
+    ) {
+        ui.horizontal(|ui| {
+            for format in ExportFormat::ALL {
+                ui.selectable_value(&mut self.format, format, format.label());
+            }
+        });
+        ui.add(TextEdit::singleline(&mut self.dir).hint_text("folder"));
+        let imported = is_imported(code);
+        if !imported {
+            ui.horizontal(|ui| {
+                day_edit(ui, &mut self.from, "from");
+                ui.label("-");
+                day_edit(ui, &mut self.to, "to");
+            });
+        }
+        let range = parse_day(&self.from).and_then(|from| Ok((from, parse_day(&self.to)?)));
+        ui.horizontal(|ui| {
+            if !imported {
+                let klines = ui.add_enabled(
+                    range.is_ok(),
+                    egui::Button::new(format!("K-lines ({})", scale.label())),
+                );
+                if klines.clicked() {
+                    if let Ok((from, to)) = range {
+                        let what = ExportWhat::Klines {
+                            code: code.to_string(),
+                            scale: scale.clone(),
+                            from,
+                            to,
+                        };
+                        self.send(tx, what, &format!("{}_{}", code, scale.label()));
+                    }
+                }
+            }
+            if ui.button("watchlist").clicked() {
+                self.send(tx, ExportWhat::Quotes, "watchlist");
+            }
+        });
+        match &self.last {
+            Some(Ok((path, rows))) => {
+                ui.label(
+                    RichText::new(format!("{} rows to {}", rows, path.display()))
+                        .small()
+                        .color(Color32::GRAY),
+                );
+            }
+            Some(Err(e)) => {
+                ui.label(RichText::new(e).small().color(Color32::RED));
+            }
+            None => {}
+        }
+    }
+}
@@ -0,0 +1,150 @@
+use std::path::Path;
+
+use eframe::{
+    egui::{self, CollapsingHeader, Grid, RichText, TextEdit},
+    epaint::Color32,
+};
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error};
+
+use super::StockTrackerApp;
+use crate::back::{
+    import::{self, CsvMapping, ImportError, IMPORTED_PREFIX},
+    stock::Stock,
+};
+
+/// Where an imported instrument's bars come from, read again at startup.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct Imported {
+    path: String,
+    mapping: CsvMapping,
+}
+
+/// The import section of the settings panel.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct ImportForm {
+    name: String,
+    path: String,
+    mapping: CsvMapping,
+    #[serde(skip)]
+    last: Option<Result<String, String>>,
+}
+
+pub(super) fn is_imported(code: &str) -> bool {
+    code.starts_with(IMPORTED_PREFIX)
+}
+
+impl StockTrackerApp {
+    /// Read the file behind `code` into its stock; returns the bar count.
+    fn load_import(&mut self, code: &str, source: &Imported) -> Result<usize, ImportError> {
+        let parsed = import::import_csv(Path::new(source.path.trim()), &source.mapping)?;
+        for e in &parsed.errors {
+            debug!("skipped imported row: {}", e);
+        }
+        let name = code.trim_start_matches(IMPORTED_PREFIX);
+        let stock = self
+            .stocks
+            .entry(code.to_string())
+            .or_insert_with(|| Stock::new(code, name));
+        stock.clear_klines();
+        // nothing older will come
+        stock.history_exhausted = true;
+        stock.set_data(import::snapshot(&parsed.items));
+        stock.set_klines(parsed.items);
+        let bars = stock.klines.len();
+        self.reset_indicators(code);
+        Ok(bars)
+    }
+
+    /// Bring back the instruments imported in earlier sessions.
+    pub(super) fn load_imports(&mut self) {
+        for (code, source) in self.setting.imports.clone() {
+            if let Err(e) = self.load_import(&code, &source) {
+                error!("import {} from {} failed: {}", code, source.path, e);
+            }
+        }
+    }
+
+    pub(super) fn import_contents(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("📥").color(Color32::LIGHT_GREEN));
+            CollapsingHeader::new("import")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let form = &mut self.setting.import;
+                    ui.add(TextEdit::singleline(&mut form.name).hint_text("name"));
+                    ui.add(TextEdit::singleline(&mut form.path).hint_text("csv file"));
+                    CollapsingHeader::new("columns")
+                        .default_open(false)
+                        .show(ui, |ui| mapping_grid(ui, &mut form.mapping));
+                    let ready = !form.name.trim().is_empty() && !form.path.trim().is_empty();
+                    if ui.add_enabled(ready, egui::Button::new("import")).clicked() {
+                        let code = format!("{}{}", IMPORTED_PREFIX, form.name.trim());
+                        let source = Imported {
+                            path: form.path.trim().to_string(),
+                            mapping: form.mapping.clone(),
+                        };
+                        let result = self.load_import(&code, &source);
+                        self.setting.import.last = Some(match result {
+                            Ok(bars) => {
+                                self.setting.imports.insert(code.clone(), source);
+                                Ok(format!("{} bars into {}", bars, code))
+                            }
+                            Err(e) => Err(e.to_string()),
+                        });
+                    }
+                    match &self.setting.import.last {
+                        Some(Ok(msg)) => {
+                            ui.label(RichText::new(msg).small().color(Color32::GRAY));
+                        }
+                        Some(Err(e)) => {
+                            ui.label(RichText::new(e).small().color(Color32::RED));
+                        }
+                        None => {}
+                    }
+                });
+        });
+    }
+}
+
+fn mapping_grid(ui: &mut egui::Ui, mapping: &mut CsvMapping) {
+    Grid::new("import_mapping").num_columns(2).show(ui, |ui| {
+        for (label, column) in [
+            ("day", &mut mapping.day),
+            ("open", &mut mapping.open),
+            ("high", &mut mapping.high),
+            ("low", &mut mapping.low),
+            ("close", &mut mapping.close),
+            ("volume", &mut mapping.volume),
+            ("amount", &mut mapping.amount),
+        ] {
+            ui.label(label);
+            ui.add(
+                TextEdit::singleline(column)
+                    .hint_text("header or 1-based column")
+                    .desired_width(100.0),
+            );
+            ui.end_row();
+        }
+        ui.label("day format");
+        ui.add(
+            TextEdit::singleline(&mut mapping.day_format)
+                .hint_text("%Y-%m-%d")
+                .desired_width(100.0),
+        )
+        .on_hover_text("chrono format, empty for 2024-09-25 or 2024-09-25 10:45:00");
+        ui.end_row();
+        ui.label("delimiter");
+        ui.horizontal(|ui| {
+            for (label, delimiter) in [(",", ','), (";", ';'), ("tab", '\t')] {
+                ui.selectable_value(&mut mapping.delimiter, delimiter, label);
+            }
+        });
+        ui.end_row();
+        ui.label("");
+        ui.checkbox(&mut mapping.has_header, "header row");
+        ui.end_row();
+    });
+}
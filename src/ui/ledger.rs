@@ -0,0 +1,374 @@
+use chrono::NaiveDate;
+use eframe::{
+    egui::{
+        self, Button, CollapsingHeader, ComboBox, DragValue, Grid, RichText, ScrollArea, TextEdit,
+    },
+    epaint::Color32,
+};
+
+use super::{
+    portfolio::{format_amount, pnl_color},
+    StockTrackerApp,
+};
+use crate::back::{
+    fees::{FeeSchedule, Side},
+    ledger::{LotMethod, Transaction, TxKind},
+    security::SecurityId,
+};
+
+/// The add-transaction row of the ledger window.
+pub(super) struct TxForm {
+    date: String,
+    code: String,
+    kind: TxKind,
+    quantity: f64,
+    price: f64,
+    fee: f64,
+    /// Fill `fee` from the fee schedule.
+    auto_fee: bool,
+    account: String,
+    error: Option<String>,
+}
+
+impl Default for TxForm {
+    fn default() -> Self {
+        Self {
+            date: chrono::Local::now().format("%Y-%m-%d").to_string(),
+            code: String::new(),
+            kind: TxKind::Buy,
+            quantity: 0.0,
+            price: 0.0,
+            fee: 0.0,
+            auto_fee: true,
+            account: String::new(),
+            error: None,
+        }
+    }
+}
+
+impl TxForm {
+    fn side(&self) -> Option<Side> {
+        match self.kind {
+            TxKind::Buy => Some(Side::Buy),
+            TxKind::Sell => Some(Side::Sell),
+            TxKind::Dividend | TxKind::Fee => None,
+        }
+    }
+
+    /// The fee the schedule charges for the order as entered so far.
+    fn estimated_fee(&self, fees: &FeeSchedule) -> Option<f64> {
+        let side = self.side()?;
+        let id = SecurityId::parse(&self.code).ok()?;
+        Some(fees.fee(&id, side, self.quantity * self.price).total())
+    }
+
+    fn to_transaction(&self, fees: &FeeSchedule) -> Result<Transaction, String> {
+        let date = NaiveDate::parse_from_str(self.date.trim(), "%Y-%m-%d")
+            .map_err(|_| format!("bad date {:?}, expected YYYY-MM-DD", self.date))?;
+        let code = SecurityId::parse(&self.code).map_err(|e| e.to_string())?;
+        if self.kind != TxKind::Fee && self.quantity <= 0.0 {
+            return Err("quantity must be positive".to_string());
+        }
+        Ok(Transaction {
+            id: 0,
+            date,
+            code: code.symbol(),
+            kind: self.kind,
+            quantity: self.quantity,
+            price: self.price,
+            fee: match self.auto_fee {
+                true => self.estimated_fee(fees).unwrap_or(self.fee),
+                false => self.fee,
+            },
+            account: self.account.trim().to_string(),
+        })
+    }
+}
+
+fn kind_label(kind: TxKind) -> &'static str {
+    match kind {
+        TxKind::Buy => "buy",
+        TxKind::Sell => "sell",
+        TxKind::Dividend => "dividend",
+        TxKind::Fee => "fee",
+    }
+}
+
+impl StockTrackerApp {
+    /// Re-derive ledger driven holdings after the ledger changed.
+    pub(super) fn sync_ledger(&mut self) {
+        // codes whose last transaction was removed go back to manual entry
+        self.setting
+            .portfolio
+            .holdings
+            .retain(|code, _| self.ledger.has_code(code) || !self.ledger_codes.contains(code));
+        self.ledger.apply_to(&mut self.setting.portfolio);
+        self.ledger_codes = self.ledger.positions().into_keys().collect();
+    }
+
+    pub(super) fn ledger_viewport(&mut self, ctx: &egui::Context) {
+        if !self.show_ledger {
+            return;
+        }
+        ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of("ledger_v"),
+            egui::ViewportBuilder::default()
+                .with_title("ledger")
+                .with_inner_size((560.0, 420.0)),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    self.ledger_contents(ui);
+                });
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.show_ledger = false;
+                }
+            },
+        );
+    }
+
+    fn ledger_contents(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label("lots");
+            let before = self.ledger.method;
+            ui.selectable_value(&mut self.ledger.method, LotMethod::Fifo, "FIFO");
+            ui.selectable_value(&mut self.ledger.method, LotMethod::Lifo, "LIFO");
+            ui.selectable_value(&mut self.ledger.method, LotMethod::Average, "average");
+            if before != self.ledger.method {
+                self.sync_ledger();
+            }
+        });
+        self.fees_contents(ui);
+        ui.separator();
+
+        ui.horizontal_wrapped(|ui| {
+            let form = &mut self.ledger_form;
+            let fees = &self.setting.fees;
+            ui.add(TextEdit::singleline(&mut form.date).desired_width(80.0));
+            ui.add(
+                TextEdit::singleline(&mut form.code)
+                    .hint_text("code")
+                    .desired_width(64.0),
+            );
+            ComboBox::from_id_salt("ledger_kind")
+                .selected_text(kind_label(form.kind))
+                .width(72.0)
+                .show_ui(ui, |ui| {
+                    for kind in [TxKind::Buy, TxKind::Sell, TxKind::Dividend, TxKind::Fee] {
+                        ui.selectable_value(&mut form.kind, kind, kind_label(kind));
+                    }
+                });
+            ui.add(
+                DragValue::new(&mut form.quantity)
+                    .range(0.0..=f64::MAX)
+                    .speed(100.0)
+                    .prefix("qty "),
+            );
+            ui.add(
+                DragValue::new(&mut form.price)
+                    .range(0.0..=f64::MAX)
+                    .speed(0.01)
+                    .max_decimals(3)
+                    .prefix("@"),
+            );
+            ui.checkbox(&mut form.auto_fee, "auto fee");
+            match form.estimated_fee(fees).filter(|_| form.auto_fee) {
+                Some(fee) => {
+                    ui.label(format!("fee {:.2}", fee));
+                }
+                None => {
+                    ui.add(
+                        DragValue::new(&mut form.fee)
+                            .range(0.0..=f64::MAX)
+                            .speed(0.1)
+                            .prefix("fee "),
+                    );
+                }
+            }
+            ui.add(
+                TextEdit::singleline(&mut form.account)
+                    .hint_text("account")
+                    .desired_width(56.0),
+            );
+            if ui.button("➕").clicked() {
+                match form.to_transaction(fees) {
+                    Ok(tx) => {
+                        self.ledger.add(tx);
+                        self.ledger_form = TxForm {
+                            date: self.ledger_form.date.clone(),
+                            account: self.ledger_form.account.clone(),
+                            ..Default::default()
+                        };
+                        self.sync_ledger();
+                    }
+                    Err(e) => form.error = Some(e),
+                }
+            }
+        });
+        if let Some(e) = &self.ledger_form.error {
+            ui.label(RichText::new(e).color(Color32::RED));
+        }
+        ui.separator();
+
+        let show_color = self.setting.show_color;
+        ui.label(RichText::new("positions").color(Color32::LIGHT_BLUE));
+        Grid::new("ledger_positions").striped(true).show(ui, |ui| {
+            ui.label("code");
+            ui.label("qty");
+            ui.label("avg cost");
+            ui.label("realized");
+            ui.end_row();
+            for (code, position) in self.ledger.positions() {
+                ui.label(&code);
+                ui.label(format!("{}", position.quantity()));
+                ui.label(format!("{:.3}", position.avg_cost()));
+                let realized = position.realized_gain();
+                ui.label(
+                    RichText::new(format_amount(realized)).color(pnl_color(realized, show_color)),
+                );
+                if ui
+                    .small_button("📈")
+                    .on_hover_text("realized history")
+                    .clicked()
+                {
+                    self.gain_history = Some(code);
+                }
+                ui.end_row();
+            }
+        });
+        ui.separator();
+
+        let mut remove = None;
+        ScrollArea::vertical().show(ui, |ui| {
+            Grid::new("ledger_transactions")
+                .striped(true)
+                .show(ui, |ui| {
+                    for tx in self.ledger.transactions().iter().rev() {
+                        ui.label(tx.date.to_string());
+                        ui.label(&tx.code);
+                        ui.label(kind_label(tx.kind));
+                        ui.label(format!("{}", tx.quantity));
+                        ui.label(format!("{:.3}", tx.price));
+                        ui.label(format!("{:.2}", tx.fee));
+                        ui.label(&tx.account);
+                        if ui
+                            .add(Button::new(RichText::new("❌").color(Color32::RED)).small())
+                            .clicked()
+                        {
+                            remove = Some(tx.id);
+                        }
+                        ui.end_row();
+                    }
+                });
+        });
+        if let Some(id) = remove {
+            self.ledger.remove(id);
+            self.sync_ledger();
+        }
+    }
+
+    fn fees_contents(&mut self, ui: &mut egui::Ui) {
+        let rate = |ui: &mut egui::Ui, value: &mut f64, label: &str| {
+            ui.label(label);
+            // edited in percent, stored as a fraction
+            let mut percent = *value * 100.0;
+            if ui
+                .add(
+                    DragValue::new(&mut percent)
+                        .range(0.0..=1.0)
+                        .speed(0.0001)
+                        .max_decimals(4)
+                        .suffix("%"),
+                )
+                .changed()
+            {
+                *value = percent / 100.0;
+            }
+        };
+        let min = |ui: &mut egui::Ui, value: &mut f64| {
+            ui.add(
+                DragValue::new(value)
+                    .range(0.0..=100.0)
+                    .speed(0.1)
+                    .prefix("min "),
+            );
+        };
+        CollapsingHeader::new("fees")
+            .default_open(false)
+            .show(ui, |ui| {
+                let fees = &mut self.setting.fees;
+                Grid::new("fee_schedule").show(ui, |ui| {
+                    rate(ui, &mut fees.commission_rate, "commission");
+                    min(ui, &mut fees.min_commission);
+                    ui.end_row();
+                    rate(ui, &mut fees.fund_commission_rate, "fund commission");
+                    min(ui, &mut fees.fund_min_commission);
+                    ui.end_row();
+                    rate(ui, &mut fees.bond_commission_rate, "bond commission");
+                    min(ui, &mut fees.bond_min_commission);
+                    ui.end_row();
+                    rate(ui, &mut fees.stamp_duty_rate, "stamp duty (sell)");
+                    ui.end_row();
+                    rate(ui, &mut fees.transfer_fee_rate, "transfer fee (sh)");
+                    ui.end_row();
+                });
+                if ui.button("reset").clicked() {
+                    *fees = FeeSchedule::default();
+                }
+            });
+    }
+
+    pub(super) fn gain_history_viewport(&mut self, ctx: &egui::Context) {
+        let Some(code) = self.gain_history.clone() else {
+            return;
+        };
+        let position = self.ledger.position(&code);
+        let name = self
+            .stocks
+            .get(&code)
+            .map(|s| s.name.clone())
+            .unwrap_or_default();
+        let show_color = self.setting.show_color;
+        ctx.show_viewport_immediate(
+            egui::ViewportId::from_hash_of("gain_history_v"),
+            egui::ViewportBuilder::default()
+                .with_title(format!("{} {} realized", name, code))
+                .with_inner_size((360.0, 300.0)),
+            |ctx, _class| {
+                egui::CentralPanel::default().show(ctx, |ui| {
+                    let total = position.realized_gain();
+                    ui.label(
+                        RichText::new(format!("realized {}", format_amount(total)))
+                            .color(pnl_color(total, show_color)),
+                    );
+                    ui.separator();
+                    ScrollArea::vertical().show(ui, |ui| {
+                        Grid::new("gain_history").striped(true).show(ui, |ui| {
+                            ui.label("date");
+                            ui.label("kind");
+                            ui.label("qty");
+                            ui.label("proceeds");
+                            ui.label("cost");
+                            ui.label("gain");
+                            ui.end_row();
+                            for r in &position.realized {
+                                ui.label(r.date.to_string());
+                                ui.label(kind_label(r.kind));
+                                ui.label(format!("{}", r.quantity));
+                                ui.label(format!("{:.2}", r.proceeds));
+                                ui.label(format!("{:.2}", r.cost));
+                                ui.label(
+                                    RichText::new(format!("{:.2}", r.gain()))
+                                        .color(pnl_color(r.gain(), show_color)),
+                                );
+                                ui.end_row();
+                            }
+                        });
+                    });
+                });
+                if ctx.input(|i| i.viewport().close_requested()) {
+                    self.gain_history = None;
+                }
+            },
+        );
+    }
+}
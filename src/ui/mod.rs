@@ -1,48 +1,131 @@
 use crate::back::stock::Stock;
-use crate::back::stock::{self, KLineScale};
-use std::{fmt::format, hash::Hash, thread, vec};
+use crate::back::alert::{Alert, AlertRule};
+use crate::back::fees::FeeSchedule;
+use crate::back::ledger::Ledger;
+use crate::back::portfolio::Portfolio;
+use crate::back::security::SecurityId;
+use crate::back::webhook::WebhookConfig;
+use crate::back::provider::record::Recorder;
+use crate::back::store::KlineStore;
+use crate::back::stock::{self, Currency, Instrument, KLineScale, QuoteParseError};
+use std::{collections::{BTreeMap, BTreeSet}, sync::Arc, thread, time::Instant, vec};
 
 use eframe::{
     egui::{
         self, ahash::HashMap, menu, Align2, Button, CentralPanel, CollapsingHeader, Context,
-        CursorIcon, Frame, Grid, Label, Layout, RichText, Separator, SidePanel, Slider, Stroke,
+        CursorIcon, DragValue, Frame, Grid, Label, Layout, RichText, Separator, SidePanel, Slider, Stroke,
         Style, TextStyle, TopBottomPanel,
     },
     emath::Align,
     epaint::{Color32, Vec2},
     App, CreationContext,
 };
-use egui_plot::{
-    AxisHints, Bar, BarChart, BoxElem, BoxPlot, BoxSpread, CoordinatesFormatter, Corner, HLine,
-    Plot, PlotPoint, Text, VLine,
-};
+use egui_plot::{Bar, BarChart, BoxElem, BoxPlot, BoxSpread, HLine, Plot, PlotPoint, Text};
 use serde::{Deserialize, Serialize};
 
 use super::back::{
     message::{ToBackend, ToFrontend},
+    provider::{FailoverProvider, SinaProvider, TencentProvider},
     Back,
 };
 use crossbeam::channel::{Receiver, Sender};
 
+mod alert;
+mod chart;
+mod export;
+mod import;
+mod ledger;
+mod portfolio;
+mod replay;
+mod watchlist;
+use alert::AlertForm;
+use chart::{intraday_chart, kline_chart, Indicators, KlineBars, Studies};
+use export::ExportForm;
+use import::{is_imported, ImportForm, Imported};
+use ledger::TxForm;
+use portfolio::{pnl_cells, pnl_color, PnlColumns};
+use replay::ReplayPanel;
+use watchlist::WatchlistForm;
+
+const MAX_PARSE_ERRORS: usize = 20;
+const LEDGER_KEY: &str = "ledger";
+/// Window title, also naming the directory eframe persists into.
+pub const APP_NAME: &str = "St Tracker";
+
 #[derive(Default)]
 pub struct StockTrackerApp {
     time: String,
     setting: Setting,
     stocks: HashMap<String, Stock>,
+    // rows the backend could not decode, newest last
+    parse_errors: Vec<(String, QuoteParseError)>,
+    ledger: Ledger,
+    // codes whose holding is derived from the ledger
+    ledger_codes: BTreeSet<String>,
+    ledger_form: TxForm,
+    show_ledger: bool,
+    gain_history: Option<String>,
+    alert_form: AlertForm,
+    alert_history: Vec<Alert>,
+    // codes whose alert fired recently, with the firing time
+    flashing: HashMap<String, Instant>,
+    replay: ReplayPanel,
+    // chart indicators per stock code, at the stock's current scale
+    indicators: HashMap<String, Indicators>,
     // Data transferring
     front_tx: Option<Sender<ToBackend>>,
     back_rx: Option<Receiver<ToFrontend>>,
 }
 
 #[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
 struct Setting {
     open: bool,
     show_name: bool,
     show_color: bool,
     hide_name: bool,
     interval: u32,
-    stocks: String,
+    #[serde(deserialize_with = "deserialize_stocks")]
+    stocks: Vec<SecurityId>,
     adding_code: String,
+    portfolio: Portfolio,
+    pnl_columns: PnlColumns,
+    fees: FeeSchedule,
+    alerts: Vec<AlertRule>,
+    alert_sound: String,
+    webhook: WebhookConfig,
+    // chart overlays and panes, per stock code
+    studies: BTreeMap<String, Studies>,
+    kline_scales: BTreeMap<String, KLineScale>,
+    kline_bars: KlineBars,
+    // write raw quote responses for replay
+    record: bool,
+    export: ExportForm,
+    // instruments read from CSV files, by code
+    imports: BTreeMap<String, Imported>,
+    import: ImportForm,
+    watchlist: WatchlistForm,
+}
+
+/// Older versions stored the watchlist as one comma-joined string.
+fn deserialize_stocks<'de, D>(deserializer: D) -> Result<Vec<SecurityId>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Stocks {
+        Joined(String),
+        List(Vec<String>),
+    }
+    let codes = match Stocks::deserialize(deserializer)? {
+        Stocks::Joined(s) => s.split(',').map(str::to_string).collect(),
+        Stocks::List(l) => l,
+    };
+    Ok(codes
+        .iter()
+        .filter_map(|x| SecurityId::parse(x).ok())
+        .collect())
 }
 
 impl StockTrackerApp {
@@ -58,11 +141,56 @@ impl StockTrackerApp {
             if let Some(setting) = eframe::get_value(storage, eframe::APP_KEY) {
                 app.setting = setting
             }
+            if let Some(ledger) = eframe::get_value(storage, LEDGER_KEY) {
+                app.ledger = ledger;
+                app.sync_ledger();
+            }
         }
+        app.load_imports();
         let codes = app.setting.stocks.clone();
-        thread::spawn(|| Back::new(back_tx, front_rx, codes).run());
+        let storage_dir = eframe::storage_dir(APP_NAME);
+        let recorder = storage_dir
+            .as_ref()
+            .map(|dir| Recorder::new(dir.join("recordings")));
+        app.replay.dir = recorder.as_ref().map(|r| r.dir().to_path_buf());
+        let sina = match recorder.clone() {
+            Some(recorder) => SinaProvider::default().with_recorder(recorder),
+            None => SinaProvider::default(),
+        };
+        let provider = Arc::new(FailoverProvider::new(
+            Arc::new(sina),
+            Arc::new(TencentProvider::default()),
+            3,
+        ));
+        let scales = app.setting.kline_scales.clone();
+        let bars = app.setting.kline_bars.0;
+        if app.setting.export.dir.is_empty() {
+            app.setting.export.dir = storage_dir
+                .as_ref()
+                .map(|dir| dir.join("exports"))
+                .unwrap_or_else(|| "exports".into())
+                .display()
+                .to_string();
+        }
+        let store = storage_dir.map(|dir| KlineStore::new(dir.join("klines")));
+        thread::spawn(move || {
+            let mut back = Back::new(back_tx, front_rx, codes, provider)
+                .with_kline_scales(scales)
+                .with_kline_bars(bars);
+            if let Some(store) = store {
+                back = back.with_store(store);
+            }
+            if let Some(recorder) = recorder {
+                back = back.with_recorder(recorder);
+            }
+            back.run()
+        });
         app.front_tx = Some(front_tx);
         app.back_rx = Some(back_rx);
+        app.send_alert_rules();
+        app.send_alert_sound();
+        app.send_webhook();
+        app.send_recording();
         app
     }
 
@@ -93,6 +221,49 @@ impl StockTrackerApp {
                     ui.add(Label::new(
                         RichText::new(self.time.clone()).text_style(egui::TextStyle::Small),
                     ));
+
+                    if !self.parse_errors.is_empty() {
+                        let warn_btn = ui
+                            .add(Button::new(
+                                RichText::new(format!("⚠{}", self.parse_errors.len()))
+                                    .text_style(TextStyle::Small)
+                                    .color(Color32::ORANGE),
+                            ))
+                            .on_hover_ui(|ui| {
+                                for (time, e) in &self.parse_errors {
+                                    ui.label(format!("{} {}", time, e));
+                                }
+                            });
+                        if warn_btn.clicked() {
+                            self.parse_errors.clear();
+                        }
+                    }
+
+                    if let Some(status) = &self.replay.status {
+                        ui.label(
+                            RichText::new(format!("⏺ {}", status.at.format("%H:%M:%S")))
+                                .text_style(TextStyle::Small)
+                                .color(Color32::LIGHT_RED),
+                        )
+                        .on_hover_text(format!("replaying {}", status.file));
+                    }
+
+                    if !self.alert_history.is_empty() {
+                        let alert_btn = ui
+                            .add(Button::new(
+                                RichText::new(format!("🔔{}", self.alert_history.len()))
+                                    .text_style(TextStyle::Small)
+                                    .color(Color32::GOLD),
+                            ))
+                            .on_hover_ui(|ui| {
+                                for alert in self.alert_history.iter().rev() {
+                                    ui.label(alert.to_string());
+                                }
+                            });
+                        if alert_btn.clicked() {
+                            self.alert_history.clear();
+                        }
+                    }
                 });
                 // controls
                 ui.with_layout(Layout::right_to_left(Align::Center), |ui| {
@@ -111,7 +282,8 @@ impl StockTrackerApp {
                             .color(Color32::GREEN),
                     ));
                     if refresh_btn.clicked() {
-                        self.stocks.clear();
+                        // imported instruments are not polled, so nothing would bring them back
+                        self.stocks.retain(|code, _| is_imported(code));
                         if let Some(tx) = &self.front_tx {
                             let _ = tx.send(ToBackend::Refresh);
                         }
@@ -136,6 +308,16 @@ impl StockTrackerApp {
 
     fn render_stocks(&mut self, ctx: &Context, ui: &mut eframe::egui::Ui) {
         ui.add_space(2.0);
+        let show_open_interest = self
+            .stocks
+            .values()
+            .any(|s| s.data_open_interest().is_some());
+        let lit = self
+            .stocks
+            .keys()
+            .filter(|code| self.flash_on(code))
+            .cloned()
+            .collect::<BTreeSet<String>>();
         Grid::new("stock_grid")
             .max_col_width(56.0)
             .min_col_width(30.0)
@@ -143,25 +325,30 @@ impl StockTrackerApp {
             .show(ui, |ui| {
                 for stock in self.stocks.values_mut().into_iter() {
                     ui.centered_and_justified(|ui| {
-                        if self.setting.show_name {
-                            ui.add(
-                                Label::new(
-                                    RichText::new(stock.name.to_string())
-                                        .text_style(egui::TextStyle::Body),
-                                )
-                                .wrap_mode(egui::TextWrapMode::Truncate),
-                            )
+                        let text = if self.setting.show_name {
+                            RichText::new(stock.name.to_string())
                         } else {
-                            ui.add(Label::new(
-                                RichText::new("   ").text_style(egui::TextStyle::Body),
-                            ))
-                        }
+                            RichText::new("   ")
+                        };
+                        let text = if lit.contains(&stock.code) {
+                            text.background_color(Color32::DARK_RED)
+                        } else {
+                            text
+                        };
+                        ui.add(
+                            Label::new(text.text_style(egui::TextStyle::Body))
+                                .wrap_mode(egui::TextWrapMode::Truncate),
+                        )
                     });
                     ui.centered_and_justified(|ui| {
-                        ui.add(Label::new(
+                        let price = ui.add(Label::new(
                             RichText::new(stock.data_new().to_string())
                                 .text_style(egui::TextStyle::Body),
                         ));
+                        // HK and US rows share the grid with A-shares
+                        if stock.data.currency != Currency::Cny {
+                            price.on_hover_text(stock.data.currency.code());
+                        }
                     });
                     let color = if self.setting.show_color {
                         match stock.data_rise_per() {
@@ -180,6 +367,32 @@ impl StockTrackerApp {
                         ))
                     });
 
+                    if self.setting.pnl_columns.any() {
+                        pnl_cells(
+                            ui,
+                            &self.setting.pnl_columns,
+                            self.setting.show_color,
+                            self.setting.portfolio.pnl(&stock.code, &stock.data),
+                        );
+                    }
+
+                    if show_open_interest {
+                        ui.centered_and_justified(|ui| {
+                            if let Instrument::Futures(f) = &stock.data.instrument {
+                                ui.add(Label::new(
+                                    RichText::new(format!("{:.0}", f.open_interest))
+                                        .text_style(egui::TextStyle::Small),
+                                ))
+                                .on_hover_text(format!(
+                                    "OI {:.0}\nsettle {}\npre settle {}",
+                                    f.open_interest, f.settlement, f.pre_settlement
+                                ));
+                            } else {
+                                ui.label("");
+                            }
+                        });
+                    }
+
                     ui.centered_and_justified(|ui| {
                         let bids_bars = stock
                             .data_bids()
@@ -242,6 +455,19 @@ impl StockTrackerApp {
                                                 p
                                             ));
                                         }
+                                        if let (Some(holding), Ok(id)) = (
+                                            self.setting.portfolio.get(&stock.code),
+                                            SecurityId::parse(&stock.code),
+                                        ) {
+                                            ui.add(Separator::default().spacing(0.0));
+                                            let break_even = self.setting.fees.break_even(
+                                                &id,
+                                                holding.quantity,
+                                                holding.quantity * holding.avg_cost,
+                                            );
+                                            ui.label(format!("break-even {:.3}", break_even))
+                                                .on_hover_text("after sell-side fees");
+                                        }
                                     });
                                 });
                                 ui.group(|ui| {
@@ -324,8 +550,12 @@ impl StockTrackerApp {
                         });
                     });
                     ui.centered_and_justified(|ui| {
-                        let boxs = stock
+                        // history loaded by scrolling back stays out of the sparkline
+                        let recent = stock
                             .klines
+                            .len()
+                            .saturating_sub(self.setting.kline_bars.0 as usize);
+                        let boxs = stock.klines[recent..]
                             .iter()
                             .enumerate()
                             .map(|(i, x)| {
@@ -368,7 +598,7 @@ impl StockTrackerApp {
                             .response;
 
                         if plot.clicked() {
-                            if let Some(tx) = &self.front_tx {
+                            if let Some(tx) = self.front_tx.as_ref().filter(|_| !is_imported(&stock.code)) {
                                 let _ = tx.send(ToBackend::StockKLine(
                                     stock.code.to_string(),
                                     stock.kline_scale.clone(),
@@ -386,125 +616,120 @@ impl StockTrackerApp {
                                     egui::CentralPanel::default().show(ctx, |ui| {
                                         ui.vertical(|ui| {
                                             ui.horizontal_wrapped(|ui| {
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline_scale,
-                                                        KLineScale::Munute5,
-                                                        "5",
-                                                    )
-                                                    .clicked()
-                                                {
-                                                    if let Some(tx) = &self.front_tx {
-                                                        let _ = tx.send(ToBackend::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Munute5,
-                                                        ));
-                                                    }
-                                                }
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline_scale,
-                                                        KLineScale::Munute15,
-                                                        "15",
-                                                    )
-                                                    .clicked()
-                                                {
-                                                    if let Some(tx) = &self.front_tx {
-                                                        let _ = tx.send(ToBackend::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Munute15,
-                                                        ));
+                                                // imported bars come at one scale only
+                                                let scales = if is_imported(&stock.code) {
+                                                    ui.label("imported");
+                                                    &[][..]
+                                                } else {
+                                                    &KLineScale::ALL[..]
+                                                };
+                                                for scale in scales.iter().cloned() {
+                                                    let label = scale.label();
+                                                    if ui
+                                                        .selectable_value(
+                                                            &mut stock.kline_scale,
+                                                            scale.clone(),
+                                                            label,
+                                                        )
+                                                        .clicked()
+                                                    {
+                                                        stock.clear_klines();
+                                                        self.indicators.remove(&stock.code);
+                                                        if let Some(tx) = &self.front_tx {
+                                                            let _ = tx.send(ToBackend::StockKLine(
+                                                                stock.code.to_string(),
+                                                                scale.clone(),
+                                                            ));
+                                                        }
+                                                        self.setting
+                                                            .kline_scales
+                                                            .insert(stock.code.clone(), scale);
                                                     }
                                                 }
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline_scale,
-                                                        KLineScale::Munute30,
-                                                        "30",
+                                                let bars = ui
+                                                    .add(
+                                                        DragValue::new(&mut self.setting.kline_bars.0)
+                                                            .range(20..=1000)
+                                                            .speed(5.0)
+                                                            .suffix(" bars"),
                                                     )
-                                                    .clicked()
-                                                {
-                                                    if let Some(tx) = &self.front_tx {
-                                                        let _ = tx.send(ToBackend::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Munute30,
-                                                        ));
-                                                    }
-                                                }
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline_scale,
-                                                        KLineScale::Day,
-                                                        "day",
-                                                    )
-                                                    .clicked()
-                                                {
+                                                    .on_hover_text("bars per request, all stocks");
+                                                if bars.drag_stopped() || bars.lost_focus() {
                                                     if let Some(tx) = &self.front_tx {
-                                                        let _ = tx.send(ToBackend::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Day,
+                                                        let _ = tx.send(ToBackend::SetKlineBars(
+                                                            self.setting.kline_bars.0,
                                                         ));
                                                     }
                                                 }
-                                                if ui
-                                                    .selectable_value(
-                                                        &mut stock.kline_scale,
-                                                        KLineScale::Week,
-                                                        "week",
+                                                ui.menu_button("💾", |ui| {
+                                                    self.setting.export.menu(
+                                                        ui,
+                                                        self.front_tx.as_ref(),
+                                                        &stock.code,
+                                                        &stock.kline_scale,
                                                     )
-                                                    .clicked()
-                                                {
-                                                    if let Some(tx) = &self.front_tx {
-                                                        let _ = tx.send(ToBackend::StockKLine(
-                                                            stock.code.to_string(),
-                                                            KLineScale::Week,
-                                                        ));
-                                                    }
+                                                })
+                                                .response
+                                                .on_hover_text("export");
+                                                if stock.kline_scale != KLineScale::Intraday {
+                                                    ui.separator();
+                                                    self.setting
+                                                        .studies
+                                                        .entry(stock.code.clone())
+                                                        .or_default()
+                                                        .toggles(ui);
                                                 }
                                             });
 
-                                            let mut x_axes = vec![];
-                                            let boxs = stock
-                                                .klines
-                                                .iter()
-                                                .enumerate()
-                                                .map(|(i, x)| {
-                                                    let x_hints = AxisHints::new_x().label("Time");
-                                                    x_axes.push(x_hints);
-
-                                                    let fill_color = if x.close < x.open {
-                                                        Color32::GREEN
-                                                    } else {
-                                                        Color32::RED
-                                                    };
-                                                    BoxElem::new(
-                                                        i as f64,
-                                                        BoxSpread::new(
-                                                            x.low,
-                                                            x.open,
-                                                            (x.open + x.close) / 2.0,
-                                                            x.close,
-                                                            x.high,
-                                                        ),
-                                                    )
-                                                    .stroke(Stroke::new(0.2, fill_color))
-                                                    .fill(fill_color.linear_multiply(0.05))
-                                                    .box_width(0.8)
-                                                })
-                                                .collect();
-                                            let box1 = BoxPlot::new(boxs);
-
-                                            Plot::new(format!("{}_kline", stock.code))
-                                                .show_background(false)
-                                                .show_grid(true)
-                                                .allow_drag([true, false])
-                                                .custom_x_axes(vec![
-                                                    AxisHints::new_x().label("Time (s)")
-                                                ])
-                                                .show(ui, |plot_ui| {
-                                                    plot_ui.box_plot(box1);
-                                                })
-                                                .response;
+                                            if stock.kline_scale == KLineScale::Intraday {
+                                                intraday_chart(
+                                                    ui,
+                                                    &stock.code,
+                                                    &stock.klines,
+                                                    stock.data.closing as f64,
+                                                );
+                                                return;
+                                            }
+                                            let studies = self
+                                                .setting
+                                                .studies
+                                                .entry(stock.code.clone())
+                                                .or_default();
+                                            let indicators = self
+                                                .indicators
+                                                .entry(stock.code.clone())
+                                                .or_insert_with(|| {
+                                                    Indicators::new(&stock.kline_scale)
+                                                });
+                                            indicators.sync(
+                                                &stock.kline_scale,
+                                                &stock.klines,
+                                                studies,
+                                            );
+                                            let wants_older = kline_chart(
+                                                ui,
+                                                &stock.code,
+                                                &stock.klines,
+                                                studies,
+                                                indicators,
+                                                &mut stock.view_shift,
+                                            );
+                                            if wants_older
+                                                && !stock.history_loading
+                                                && !stock.history_exhausted
+                                            {
+                                                if let (Some(tx), Some(first)) =
+                                                    (&self.front_tx, stock.klines.first())
+                                                {
+                                                    stock.history_loading = true;
+                                                    let _ = tx.send(ToBackend::StockKLineHistory(
+                                                        stock.code.clone(),
+                                                        stock.kline_scale.clone(),
+                                                        first.day,
+                                                        stock.klines.len() as u32,
+                                                    ));
+                                                }
+                                            }
                                         });
                                     });
 
@@ -519,6 +744,33 @@ impl StockTrackerApp {
 
                     ui.end_row();
                 }
+
+                if self.setting.pnl_columns.any() {
+                    let totals = self
+                        .setting
+                        .portfolio
+                        .totals(self.stocks.values().map(|s| (s.code.as_str(), &s.data)));
+                    for (currency, total) in totals {
+                        ui.centered_and_justified(|ui| {
+                            ui.label(RichText::new("Σ").color(Color32::GOLD))
+                                .on_hover_text(currency.code());
+                        });
+                        ui.label("");
+                        ui.centered_and_justified(|ui| {
+                            ui.label(
+                                RichText::new(format!("{:.2}", total.total_per()))
+                                    .color(pnl_color(total.total, self.setting.show_color)),
+                            );
+                        });
+                        pnl_cells(
+                            ui,
+                            &self.setting.pnl_columns,
+                            self.setting.show_color,
+                            Some(total),
+                        );
+                        ui.end_row();
+                    }
+                }
             });
     }
 
@@ -581,6 +833,21 @@ impl StockTrackerApp {
         });
         ui.add(Separator::default().spacing(0.0));
 
+        self.holdings_contents(ui);
+        ui.add(Separator::default().spacing(0.0));
+
+        self.alerts_contents(ui);
+        ui.add(Separator::default().spacing(0.0));
+
+        self.replay_contents(ui);
+        ui.add(Separator::default().spacing(0.0));
+
+        self.import_contents(ui);
+        ui.add(Separator::default().spacing(0.0));
+
+        self.watchlist_contents(ui);
+        ui.add(Separator::default().spacing(0.0));
+
         ui.horizontal(|ui| {
             ui.label(RichText::new("📓").color(Color32::LIGHT_BLUE));
             CollapsingHeader::new("stocks")
@@ -601,6 +868,12 @@ impl StockTrackerApp {
                                 ));
                                 if close_btn.clicked() {
                                     self.stocks.remove(&s.code);
+                                    self.setting.imports.remove(&s.code);
+                                    self.indicators.remove(&s.code);
+                                    // ledger driven holdings follow the ledger
+                                    if !self.ledger_codes.contains(&s.code) {
+                                        self.setting.portfolio.holdings.remove(&s.code);
+                                    }
                                     if let Some(tx) = &self.front_tx {
                                         let _ = tx.send(ToBackend::StockDel(s.code.clone()));
                                     };
@@ -622,7 +895,7 @@ impl StockTrackerApp {
                     },
                 ..
             } = self;
-            let text_color = if stock::check_stock_code(&code) {
+            let text_color = if stock::check_stock_code(code) {
                 Color32::GREEN
             } else {
                 Color32::WHITE
@@ -633,9 +906,9 @@ impl StockTrackerApp {
             );
 
             if response.lost_focus() || ctx.input(|i| i.key_pressed(egui::Key::Enter)) {
-                if stock::check_stock_code(&code) {
+                if let Ok(id) = SecurityId::parse(code) {
                     if let Some(tx) = &self.front_tx {
-                        tx.send(ToBackend::StockAdd(code.to_string()))
+                        tx.send(ToBackend::StockAdd(id))
                             .expect("Failed sending  add stock event .");
                         *code = String::new();
                         *open = false;
@@ -651,6 +924,22 @@ impl StockTrackerApp {
     fn update_time(&mut self) {
         self.time = format!("{}", chrono::Local::now().format("%Y-%m-%d %H:%M:%S"));
     }
+
+    /// Recompute the chart indicators of `code` from its bars next frame.
+    fn reset_indicators(&mut self, code: &str) {
+        if let Some(indicators) = self.indicators.get_mut(code) {
+            indicators.reset();
+        }
+    }
+
+    /// The K-line scale last picked for `code`.
+    fn scale_of(&self, code: &str) -> KLineScale {
+        self.setting
+            .kline_scales
+            .get(code)
+            .cloned()
+            .unwrap_or_default()
+    }
 }
 
 impl App for StockTrackerApp {
@@ -663,9 +952,12 @@ impl App for StockTrackerApp {
                         self.update_time();
                         list.iter().for_each(|(code, name, base_data)| {
                             if let Some(s) = self.stocks.get_mut(code) {
+                                s.set_name(name);
                                 s.set_data(base_data.clone());
-                            } else {
+                            } else if self.replay.status.is_none() {
+                                // a recording may hold codes since removed
                                 let mut s = Stock::new(&code, &name);
+                                s.kline_scale = self.scale_of(code);
                                 s.set_data(base_data.clone());
                                 self.stocks.insert(code.to_string(), s);
                             }
@@ -673,18 +965,58 @@ impl App for StockTrackerApp {
                     }
                     ToFrontend::Data(code, name, base_data) => {
                         if let Some(s) = self.stocks.get_mut(&code) {
+                            s.set_name(&name);
                             s.set_data(base_data);
                         } else {
                             let mut s = Stock::new(&code, &name);
+                            s.kline_scale = self.scale_of(&code);
                             s.set_data(base_data);
                             self.stocks.insert(code, s);
                         }
                     }
-                    ToFrontend::Kline(code, klines) => {
+                    ToFrontend::Kline(code, scale, klines) => {
+                        if let Some(s) = self.stocks.get_mut(&code) {
+                            if s.kline_scale == scale {
+                                // more than the last bar may have been replaced
+                                s.set_klines(klines);
+                                self.reset_indicators(&code);
+                            }
+                        }
+                    }
+                    ToFrontend::KlineHistory(code, scale, klines, exhausted) => {
+                        if let Some(s) = self.stocks.get_mut(&code) {
+                            if s.kline_scale == scale {
+                                s.history_loading = false;
+                                s.history_exhausted = exhausted;
+                                match s.prepend_klines(klines) {
+                                    0 => s.history_exhausted = true,
+                                    added => {
+                                        s.view_shift += added;
+                                        self.reset_indicators(&code);
+                                    }
+                                }
+                            }
+                        }
+                    }
+                    ToFrontend::KlineBar(code, scale, bar) => {
                         if let Some(s) = self.stocks.get_mut(&code) {
-                            s.set_klines(klines)
+                            if s.kline_scale == scale {
+                                s.merge_kline(bar)
+                            }
+                        }
+                    }
+                    ToFrontend::ParseErrors(errors) => {
+                        let time = chrono::Local::now().format("%H:%M:%S").to_string();
+                        for e in errors {
+                            self.parse_errors.retain(|(_, x)| x != &e);
+                            self.parse_errors.push((time.clone(), e));
                         }
+                        let overflow = self.parse_errors.len().saturating_sub(MAX_PARSE_ERRORS);
+                        self.parse_errors.drain(..overflow);
                     }
+                    ToFrontend::Alert(alert) => self.on_alert(alert),
+                    ToFrontend::Replay(status) => self.replay.status = status,
+                    ToFrontend::Exported(result) => self.setting.export.last = Some(result),
                 },
                 Err(err) => {
                     let _ = err;
@@ -699,18 +1031,20 @@ impl App for StockTrackerApp {
                 self.render_stocks(ctx, ui);
             });
         self.setting_panel(ctx);
+        self.ledger_viewport(ctx);
+        self.gain_history_viewport(ctx);
+        self.watchlist_preview(ctx);
     }
 
     fn save(&mut self, storage: &mut dyn eframe::Storage) {
         let codes = self
             .stocks
             .values()
-            .into_iter()
-            .map(|s| s.code.clone())
-            .collect::<Vec<String>>()
-            .join(",");
+            .filter_map(|s| SecurityId::parse(&s.code).ok())
+            .collect::<Vec<SecurityId>>();
         self.setting.stocks = codes;
         eframe::set_value(storage, eframe::APP_KEY, &self.setting);
+        eframe::set_value(storage, LEDGER_KEY, &self.ledger);
     }
 }
 
@@ -0,0 +1,163 @@
+use eframe::{
+    egui::{self, CollapsingHeader, DragValue, Label, Layout, RichText, TextEdit},
+    emath::Align,
+    epaint::Color32,
+};
+use serde::{Deserialize, Serialize};
+
+use super::StockTrackerApp;
+use crate::back::portfolio::Pnl;
+
+/// Which holding columns the grid shows.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct PnlColumns {
+    pub market_value: bool,
+    pub day: bool,
+    pub total: bool,
+}
+
+impl PnlColumns {
+    pub fn any(&self) -> bool {
+        self.market_value || self.day || self.total
+    }
+}
+
+/// `12345678.0` -> `1234.6万`, keeping the narrow grid cells readable.
+pub(super) fn format_amount(v: f64) -> String {
+    match v.abs() {
+        a if a >= 1e8 => format!("{:.2}亿", v / 1e8),
+        a if a >= 1e4 => format!("{:.1}万", v / 1e4),
+        _ => format!("{:.2}", v),
+    }
+}
+
+pub(super) fn pnl_color(v: f64, show_color: bool) -> Color32 {
+    match v {
+        _ if !show_color => Color32::WHITE,
+        v if v < 0.0 => Color32::GREEN,
+        v if v > 0.0 => Color32::RED,
+        _ => Color32::WHITE,
+    }
+}
+
+/// One cell per enabled column; blank cells for codes without a holding.
+pub(super) fn pnl_cells(
+    ui: &mut egui::Ui,
+    columns: &PnlColumns,
+    show_color: bool,
+    pnl: Option<Pnl>,
+) {
+    let cell = |ui: &mut egui::Ui, text: String, color: Color32| {
+        ui.centered_and_justified(|ui| {
+            ui.add(Label::new(
+                RichText::new(text)
+                    .text_style(egui::TextStyle::Small)
+                    .color(color),
+            ));
+        });
+    };
+    let Some(pnl) = pnl else {
+        let n = [columns.market_value, columns.day, columns.total]
+            .iter()
+            .filter(|x| **x)
+            .count();
+        (0..n).for_each(|_| cell(ui, String::new(), Color32::WHITE));
+        return;
+    };
+    if columns.market_value {
+        cell(ui, format_amount(pnl.market_value), Color32::WHITE);
+    }
+    if columns.day {
+        cell(ui, format_amount(pnl.day), pnl_color(pnl.day, show_color));
+    }
+    if columns.total {
+        cell(
+            ui,
+            format_amount(pnl.total),
+            pnl_color(pnl.total, show_color),
+        );
+    }
+}
+
+impl StockTrackerApp {
+    pub(super) fn holdings_contents(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("💼").color(Color32::GOLD));
+            let columns = &mut self.setting.pnl_columns;
+            ui.checkbox(&mut columns.market_value, "value");
+            ui.checkbox(&mut columns.day, "day");
+            ui.checkbox(&mut columns.total, "total");
+            if ui.button("📒").on_hover_text("ledger").clicked() {
+                self.show_ledger = !self.show_ledger;
+            }
+        });
+        CollapsingHeader::new("holdings")
+            .default_open(false)
+            .show(ui, |ui| {
+                let mut codes = self.stocks.keys().cloned().collect::<Vec<String>>();
+                codes.sort();
+                for code in codes {
+                    let name = self.stocks[&code].name.clone();
+                    if self.ledger_codes.contains(&code) {
+                        let holding = self
+                            .setting
+                            .portfolio
+                            .get(&code)
+                            .cloned()
+                            .unwrap_or_default();
+                        ui.with_layout(Layout::left_to_right(Align::Center), |ui| {
+                            ui.label(RichText::new(name).color(Color32::LIGHT_BLUE))
+                                .on_hover_text(&code);
+                            ui.label(format!("qty {} @{:.3}", holding.quantity, holding.avg_cost))
+                                .on_hover_text("from ledger");
+                            if ui
+                                .small_button("📈")
+                                .on_hover_text("realized history")
+                                .clicked()
+                            {
+                                self.gain_history = Some(code.clone());
+                            }
+                        });
+                        continue;
+                    }
+                    // edit a copy, so codes nobody touched get no holding
+                    let mut holding = self
+                        .setting
+                        .portfolio
+                        .holdings
+                        .get(&code)
+                        .cloned()
+                        .unwrap_or_default();
+                    let changed = ui
+                        .with_layout(Layout::left_to_right(Align::Center), |ui| {
+                            ui.label(RichText::new(name).color(Color32::LIGHT_BLUE))
+                                .on_hover_text(&code);
+                            let quantity = ui.add(
+                                DragValue::new(&mut holding.quantity)
+                                    .range(0.0..=f64::MAX)
+                                    .speed(100.0)
+                                    .prefix("qty "),
+                            );
+                            let avg_cost = ui.add(
+                                DragValue::new(&mut holding.avg_cost)
+                                    .range(0.0..=f64::MAX)
+                                    .speed(0.01)
+                                    .max_decimals(3)
+                                    .prefix("@"),
+                            );
+                            let account = ui.add(
+                                TextEdit::singleline(&mut holding.account)
+                                    .hint_text("account")
+                                    .desired_width(48.0),
+                            );
+                            quantity.changed() || avg_cost.changed() || account.changed()
+                        })
+                        .inner;
+                    if changed {
+                        self.setting.portfolio.holdings.insert(code, holding);
+                    }
+                }
+            });
+    }
+}
@@ -0,0 +1,134 @@
+use std::path::PathBuf;
+
+use eframe::{
+    egui::{self, Button, CollapsingHeader, ComboBox, RichText, Slider},
+    epaint::Color32,
+};
+
+use super::StockTrackerApp;
+use crate::back::{
+    message::ToBackend,
+    provider::{
+        record,
+        replay::{ReplayCommand, ReplayStatus, Speed},
+    },
+};
+
+/// Recording switch, recording picker and, while replaying, the transport.
+#[derive(Default)]
+pub(super) struct ReplayPanel {
+    /// Where the recorder writes, if there is a storage directory.
+    pub(super) dir: Option<PathBuf>,
+    pub(super) status: Option<ReplayStatus>,
+    recordings: Vec<PathBuf>,
+    picked: Option<PathBuf>,
+}
+
+fn file_name(path: &std::path::Path) -> String {
+    path.file_name()
+        .map(|x| x.to_string_lossy().into_owned())
+        .unwrap_or_default()
+}
+
+impl StockTrackerApp {
+    fn send_replay(&self, msg: ToBackend) {
+        if let Some(tx) = &self.front_tx {
+            let _ = tx.send(msg);
+        }
+    }
+
+    pub(super) fn send_recording(&self) {
+        self.send_replay(ToBackend::SetRecording(self.setting.record));
+    }
+
+    pub(super) fn replay_contents(&mut self, ui: &mut egui::Ui) {
+        let Some(dir) = self.replay.dir.clone() else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("⏺").color(Color32::LIGHT_RED));
+            CollapsingHeader::new("replay")
+                .default_open(false)
+                .show(ui, |ui| {
+                    if ui
+                        .checkbox(&mut self.setting.record, "record quotes")
+                        .on_hover_text(dir.display().to_string())
+                        .changed()
+                    {
+                        self.send_recording();
+                    }
+                    ui.horizontal(|ui| {
+                        let picked = self
+                            .replay
+                            .picked
+                            .as_deref()
+                            .map(file_name)
+                            .unwrap_or_default();
+                        let combo = ComboBox::from_id_salt("recordings")
+                            .selected_text(picked)
+                            .show_ui(ui, |ui| {
+                                for path in self.replay.recordings.iter().rev() {
+                                    ui.selectable_value(
+                                        &mut self.replay.picked,
+                                        Some(path.clone()),
+                                        file_name(path),
+                                    );
+                                }
+                            });
+                        if combo.response.clicked() {
+                            self.replay.recordings = record::recordings(&dir);
+                        }
+                        let open = ui.add_enabled(self.replay.picked.is_some(), Button::new("⏵"));
+                        if open.on_hover_text("replay").clicked() {
+                            if let Some(path) = self.replay.picked.clone() {
+                                self.send_replay(ToBackend::StartReplay(path));
+                            }
+                        }
+                        if self.replay.status.is_some() && ui.button("⏹").clicked() {
+                            self.send_replay(ToBackend::StopReplay);
+                        }
+                    });
+                    self.transport(ui);
+                });
+        });
+    }
+
+    fn transport(&mut self, ui: &mut egui::Ui) {
+        let Some(status) = self.replay.status.clone() else {
+            return;
+        };
+        ui.horizontal(|ui| {
+            let pause = if status.paused { "⏵" } else { "⏸" };
+            if ui.button(pause).clicked() {
+                self.send_replay(ToBackend::Replay(ReplayCommand::Pause(!status.paused)));
+            }
+            for speed in Speed::ALL {
+                if ui
+                    .selectable_label(status.speed == speed, speed.label())
+                    .clicked()
+                {
+                    self.send_replay(ToBackend::Replay(ReplayCommand::Speed(speed)));
+                }
+            }
+        });
+        let mut position = status.position;
+        let seek = ui.add(
+            Slider::new(&mut position, 0..=status.len.saturating_sub(1))
+                .show_value(false)
+                .text(status.at.format("%m-%d %H:%M:%S").to_string()),
+        );
+        if seek.drag_stopped() || (seek.changed() && !seek.dragged()) {
+            self.send_replay(ToBackend::Replay(ReplayCommand::Seek(position)));
+        }
+        ui.label(
+            RichText::new(format!(
+                "{} {}/{}",
+                status.file,
+                status.position + 1,
+                status.len
+            ))
+            .small()
+            .color(Color32::GRAY),
+        );
+    }
+}
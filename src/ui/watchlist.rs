@@ -0,0 +1,220 @@
+use std::{collections::HashSet, fs, path::Path};
+
+use eframe::{
+    egui::{self, CollapsingHeader, ComboBox, Grid, RichText, ScrollArea, TextEdit},
+    epaint::Color32,
+};
+use serde::{Deserialize, Serialize};
+
+use super::StockTrackerApp;
+use crate::back::{
+    message::ToBackend,
+    security::SecurityId,
+    watchlist::{self, Entry, WatchlistFormat},
+};
+
+#[derive(Clone, PartialEq)]
+enum Status {
+    New,
+    /// Already in the watchlist.
+    Watched,
+    /// Earlier in the same file.
+    Repeated,
+    Invalid(String),
+}
+
+impl Status {
+    fn text(&self) -> RichText {
+        match self {
+            Status::New => RichText::new("new").color(Color32::GREEN),
+            Status::Watched => RichText::new("watched").color(Color32::GRAY),
+            Status::Repeated => RichText::new("repeated").color(Color32::GRAY),
+            Status::Invalid(e) => RichText::new(e).color(Color32::RED),
+        }
+    }
+}
+
+struct Row {
+    entry: Entry,
+    status: Status,
+    pick: bool,
+}
+
+/// The watchlist section of the settings panel and its import preview.
+#[derive(Default, Serialize, Deserialize)]
+#[serde(default)]
+pub(super) struct WatchlistForm {
+    format: WatchlistFormat,
+    path: String,
+    #[serde(skip)]
+    preview: Option<Vec<Row>>,
+    #[serde(skip)]
+    last: Option<Result<String, String>>,
+}
+
+impl StockTrackerApp {
+    /// Watched instruments with their names, by code.
+    fn watched(&self) -> Vec<(SecurityId, String)> {
+        let mut items = self
+            .stocks
+            .values()
+            .filter_map(|s| Some((SecurityId::parse(&s.code).ok()?, s.name.clone())))
+            .collect::<Vec<(SecurityId, String)>>();
+        items.sort_by_key(|(id, _)| id.symbol());
+        items
+    }
+
+    /// Read the file and mark each entry new, already there or invalid.
+    fn preview_watchlist(&self) -> Result<Vec<Row>, String> {
+        let form = &self.setting.watchlist;
+        let bytes = fs::read(Path::new(form.path.trim())).map_err(|e| e.to_string())?;
+        let mut seen = HashSet::new();
+        let rows = watchlist::parse(form.format, &watchlist::decode(&bytes))
+            .into_iter()
+            .map(|entry| {
+                let status = match &entry.id {
+                    Err(e) => Status::Invalid(e.to_string()),
+                    Ok(id) if self.stocks.contains_key(&id.symbol()) => Status::Watched,
+                    Ok(id) if !seen.insert(id.symbol()) => Status::Repeated,
+                    Ok(_) => Status::New,
+                };
+                Row {
+                    pick: status == Status::New,
+                    entry,
+                    status,
+                }
+            })
+            .collect();
+        Ok(rows)
+    }
+
+    fn export_watchlist(&self) -> Result<String, String> {
+        let form = &self.setting.watchlist;
+        let items = self.watched();
+        let (bytes, skipped) = watchlist::render(form.format, &items);
+        let path = Path::new(form.path.trim());
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir).map_err(|e| e.to_string())?;
+        }
+        fs::write(path, bytes).map_err(|e| e.to_string())?;
+        let written = items.len() - skipped;
+        Ok(match skipped {
+            0 => format!("{} codes to {}", written, path.display()),
+            _ => format!(
+                "{} codes to {}, {} not A-shares left out",
+                written,
+                path.display(),
+                skipped
+            ),
+        })
+    }
+
+    pub(super) fn watchlist_contents(&mut self, ui: &mut egui::Ui) {
+        ui.horizontal(|ui| {
+            ui.label(RichText::new("📋").color(Color32::LIGHT_BLUE));
+            CollapsingHeader::new("watchlist")
+                .default_open(false)
+                .show(ui, |ui| {
+                    let form = &mut self.setting.watchlist;
+                    ComboBox::from_id_salt("watchlist_format")
+                        .selected_text(form.format.label())
+                        .show_ui(ui, |ui| {
+                            for format in WatchlistFormat::ALL {
+                                ui.selectable_value(&mut form.format, format, format.label());
+                            }
+                        });
+                    ui.add(
+                        TextEdit::singleline(&mut form.path)
+                            .hint_text(format!("file.{}", form.format.extension())),
+                    );
+                    let ready = !form.path.trim().is_empty();
+                    ui.horizontal(|ui| {
+                        if ui.add_enabled(ready, egui::Button::new("import")).clicked() {
+                            match self.preview_watchlist() {
+                                Ok(rows) => {
+                                    self.setting.watchlist.preview = Some(rows);
+                                    self.setting.watchlist.last = None;
+                                }
+                                Err(e) => self.setting.watchlist.last = Some(Err(e)),
+                            }
+                        }
+                        if ui.add_enabled(ready, egui::Button::new("export")).clicked() {
+                            self.setting.watchlist.last = Some(self.export_watchlist());
+                        }
+                    });
+                    match &self.setting.watchlist.last {
+                        Some(Ok(msg)) => {
+                            ui.label(RichText::new(msg).small().color(Color32::GRAY));
+                        }
+                        Some(Err(e)) => {
+                            ui.label(RichText::new(e).small().color(Color32::RED));
+                        }
+                        None => {}
+                    }
+                });
+        });
+    }
+
+    /// Pick which of the imported codes to add.
+    pub(super) fn watchlist_preview(&mut self, ctx: &egui::Context) {
+        let Some(rows) = self.setting.watchlist.preview.as_mut() else {
+            return;
+        };
+        let mut open = true;
+        let mut merge = false;
+        egui::Window::new("import watchlist")
+            .open(&mut open)
+            .collapsible(false)
+            .show(ctx, |ui| {
+                ScrollArea::vertical().max_height(300.0).show(ui, |ui| {
+                    Grid::new("watchlist_preview").striped(true).show(ui, |ui| {
+                        for row in rows.iter_mut() {
+                            let code = match &row.entry.id {
+                                Ok(id) => id.symbol(),
+                                Err(_) => row.entry.raw.clone(),
+                            };
+                            ui.add_enabled(
+                                row.entry.id.is_ok(),
+                                egui::Checkbox::without_text(&mut row.pick),
+                            );
+                            ui.label(code).on_hover_text(&row.entry.raw);
+                            ui.label(&row.entry.name);
+                            ui.label(row.status.text());
+                            ui.end_row();
+                        }
+                    });
+                });
+                ui.separator();
+                let picked = rows.iter().filter(|r| r.pick).count();
+                ui.horizontal(|ui| {
+                    if ui.button("all new").clicked() {
+                        rows.iter_mut()
+                            .for_each(|r| r.pick = r.status == Status::New);
+                    }
+                    if ui.button("none").clicked() {
+                        rows.iter_mut().for_each(|r| r.pick = false);
+                    }
+                    merge = ui
+                        .add_enabled(picked > 0, egui::Button::new(format!("merge {}", picked)))
+                        .clicked();
+                });
+            });
+        if merge {
+            let ids = rows
+                .iter()
+                .filter(|r| r.pick)
+                .filter_map(|r| r.entry.id.clone().ok())
+                .collect::<Vec<SecurityId>>();
+            let added = ids.len();
+            if let Some(tx) = &self.front_tx {
+                for id in ids {
+                    let _ = tx.send(ToBackend::StockAdd(id));
+                }
+            }
+            self.setting.watchlist.last = Some(Ok(format!("{} codes added", added)));
+        }
+        if merge || !open {
+            self.setting.watchlist.preview = None;
+        }
+    }
+}